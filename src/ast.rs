@@ -1,40 +1,315 @@
-use crate::{token::{Token, Object}, error::{CblResult, Error}};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+use crate::{token::{json_string, Token, TokenType, Object}, error::CblResult};
+
+static NEXT_EXPR_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A fresh id for expressions which the resolver records a scope depth
+/// for. Ids are unique for the whole process, so REPL lines parsed
+/// separately never collide
+pub fn next_expr_id() -> usize {
+    NEXT_EXPR_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
+    /// A list of values, eg. "[1, 2, 3]"
+    Array { elements: Vec<Expr> },
+    /// Assigns a new value to an existing variable, eg. "a = 5"
+    Assign { id: usize, name: Token, value: Box<Expr> },
     /// Expressions with 2 operands and 1 operator
     Binary {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
     },
+    /// Calls a function with a list of arguments, eg. "clock()". The
+    /// closing parenthesis is kept to report errors on the right line
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
+    /// Updates a variable, element or field with a binary operator, eg.
+    /// "a[i] += 1". The object and index are only evaluated once. The
+    /// operator is the one applied, eg. "+" for "+="
+    CompoundAssign {
+        target: Box<Expr>,
+        operator: Token,
+        value: Box<Expr>,
+    },
+    /// Reads a property of a value, eg. "s.length" or the "s.upper" in
+    /// "s.upper()". Only strings have properties for now
+    Get { object: Box<Expr>, name: Token },
     /// Grouped expressions like (1 + 2) * 3
     /// useful for overiding precedence
     Grouping { expression: Box<Expr> },
+    /// Reads one element of an array, eg. "a[0]". The closing bracket is
+    /// kept to report errors on the right line
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    /// Sets one element of an array or map, eg. "a[0] = 5"
+    IndexAssign {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
     /// Literal expressions like 1, 2, 3, 4, 5, 6, 7, 8, 9, 0
     Literal { value: Object },
+    /// A map from string keys to values, eg. "{"a": 1}". Only parsed
+    /// where an expression is expected, a statement starting with "{"
+    /// is always a block. The opening brace is kept for errors
+    Map { brace: Token, entries: Vec<(Expr, Expr)> },
+    /// Short-circuiting "and" / "or" / "??" expressions, only evaluates
+    /// the right operand when the left one doesn't decide the result
+    Logical {
+        left: Box<Expr>,
+        operator: Token,
+        right: Box<Expr>,
+    },
+    /// Increments or decrements a variable, element or field, giving its old
+    /// value, eg. "i++"
+    Postfix { target: Box<Expr>, operator: Token },
+    /// Sets a field of an instance, eg. "point.x = 1"
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        value: Box<Expr>,
+    },
+    /// A method of the superclass bound to `this`, eg. "super.init".
+    /// Errors point at the keyword, so only the method's name is kept
+    Super { id: usize, keyword: Token, method: String },
+    /// The instance a method was called on. It is looked up like a
+    /// variable, so it has an id for the resolver too
+    This { id: usize, keyword: Token },
     /// Expressions with a single operator, eg. "-" in "-1"
     Unary { operator: Token, right: Box<Expr> },
+    /// References to a previously declared variable, eg. "a" in "a + 1"
+    Variable { id: usize, name: Token },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    /// Fails with a runtime error when the condition is falsey, eg.
+    /// "assert a == 1, "a should be 1";". The message is optional
+    Assert {
+        keyword: Token,
+        condition: Expr,
+        message: Option<Expr>,
+    },
+    /// A list of statements with their own scope, eg. "{ var a = 1; }"
+    Block { line: u32, statements: Vec<Stmt> },
+    /// Leaves the innermost loop straight away
+    Break { keyword: Token },
+    /// Declares a variable which can't be assigned to again, eg.
+    /// "const PI = 3.14;". Unlike `var` the initializer is required
+    Const { name: Token, initializer: Expr },
+    /// Skips the rest of the innermost loop's body, a for loop still
+    /// runs its increment
+    Continue { keyword: Token },
+    /// Expressions evaluated for their side effects, eg. "1 + 2;"
+    Expression { line: u32, expression: Expr },
+    /// Class declarations, eg. "class Point { init(x) { this.x = x; } }".
+    /// The superclass, from "class B < A", is always an `Expr::Variable`
+    /// and every method is a `Stmt::Function`
+    Class {
+        name: Token,
+        superclass: Option<Expr>,
+        methods: Vec<Stmt>,
+    },
+    /// Runs the body once for each element of an array or character of
+    /// a string, eg. "for (x in [1, 2]) print x;". Every iteration gets
+    /// a new scope holding `name`
+    ForEach {
+        name: Token,
+        iterable: Expr,
+        body: Box<Stmt>,
+    },
+    /// Function declarations, eg. "fun add(a, b) { return a + b; }"
+    Function {
+        name: Token,
+        params: Vec<Token>,
+        body: Vec<Stmt>,
+    },
+    /// Conditionally runs one of two branches, eg. "if (a) print 1; else print 2;"
+    If {
+        line: u32,
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+    /// Evaluates an expression and prints the result, eg. "print 1 + 2;"
+    Print { line: u32, expression: Expr },
+    /// Leaves the current function, `return;` gives back nil
+    Return { keyword: Token, value: Option<Expr> },
+    /// Variable declarations with an optional initializer, eg. "var a = 1;"
+    Var { name: Token, initializer: Option<Expr> },
+    /// Runs the body for as long as the condition is truthy. For loops
+    /// are turned into a while loop with an increment which runs after
+    /// the body, even when it is cut short by `continue`
+    While {
+        line: u32,
+        condition: Expr,
+        body: Box<Stmt>,
+        increment: Option<Expr>,
+    },
 }
 
 pub trait Visitor<R> {
-    fn visit_binary_expr(&self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<R>;
-    fn visit_grouping_expr(&self, expression: &Expr) -> CblResult<R>;
-    fn visit_literal_expr(&self, value: &Object) -> CblResult<R>;
-    fn visit_unary_expr(&self, operator: &Token, right: &Expr) -> CblResult<R>;
+    fn visit_array_expr(&mut self, elements: &[Expr]) -> CblResult<R>;
+    fn visit_assign_expr(&mut self, id: usize, name: &Token, value: &Expr) -> CblResult<R>;
+    fn visit_binary_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<R>;
+    fn visit_call_expr(&mut self, callee: &Expr, paren: &Token, arguments: &[Expr]) -> CblResult<R>;
+    fn visit_compound_assign_expr(&mut self, target: &Expr, operator: &Token, value: &Expr) -> CblResult<R>;
+    fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> CblResult<R>;
+    fn visit_grouping_expr(&mut self, expression: &Expr) -> CblResult<R>;
+    fn visit_index_expr(&mut self, object: &Expr, bracket: &Token, index: &Expr) -> CblResult<R>;
+    fn visit_index_assign_expr(&mut self, object: &Expr, bracket: &Token, index: &Expr, value: &Expr) -> CblResult<R>;
+    fn visit_literal_expr(&mut self, value: &Object) -> CblResult<R>;
+    fn visit_logical_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<R>;
+    fn visit_map_expr(&mut self, brace: &Token, entries: &[(Expr, Expr)]) -> CblResult<R>;
+    fn visit_postfix_expr(&mut self, target: &Expr, operator: &Token) -> CblResult<R>;
+    fn visit_set_expr(&mut self, object: &Expr, name: &Token, value: &Expr) -> CblResult<R>;
+    fn visit_super_expr(&mut self, id: usize, keyword: &Token, method: &str) -> CblResult<R>;
+    fn visit_this_expr(&mut self, id: usize, keyword: &Token) -> CblResult<R>;
+    fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> CblResult<R>;
+    fn visit_variable_expr(&mut self, id: usize, name: &Token) -> CblResult<R>;
+}
+
+pub trait StmtVisitor<R> {
+    fn visit_assert_stmt(&mut self, keyword: &Token, condition: &Expr, message: &Option<Expr>) -> CblResult<R>;
+    fn visit_block_stmt(&mut self, statements: &[Stmt]) -> CblResult<R>;
+    fn visit_break_stmt(&mut self, keyword: &Token) -> CblResult<R>;
+    fn visit_class_stmt(&mut self, name: &Token, superclass: &Option<Expr>, methods: &[Stmt]) -> CblResult<R>;
+    fn visit_const_stmt(&mut self, name: &Token, initializer: &Expr) -> CblResult<R>;
+    fn visit_continue_stmt(&mut self, keyword: &Token) -> CblResult<R>;
+    fn visit_expression_stmt(&mut self, expression: &Expr) -> CblResult<R>;
+    fn visit_for_each_stmt(&mut self, name: &Token, iterable: &Expr, body: &Stmt) -> CblResult<R>;
+    fn visit_function_stmt(&mut self, name: &Token, params: &[Token], body: &[Stmt]) -> CblResult<R>;
+    fn visit_if_stmt(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> CblResult<R>;
+    fn visit_print_stmt(&mut self, expression: &Expr) -> CblResult<R>;
+    fn visit_return_stmt(&mut self, keyword: &Token, value: &Option<Expr>) -> CblResult<R>;
+    fn visit_var_stmt(&mut self, name: &Token, initializer: &Option<Expr>) -> CblResult<R>;
+    fn visit_while_stmt(&mut self, condition: &Expr, body: &Stmt, increment: &Option<Expr>) -> CblResult<R>;
 }
 
 impl Expr {
     /// Based on expresion type, call the appropriate visitor method
-    pub fn accept<R>(&self, visitor: &dyn Visitor<R>) -> CblResult<R> {
+    pub fn accept<R>(&self, visitor: &mut dyn Visitor<R>) -> CblResult<R> {
         match self {
+            Expr::Array { elements } => visitor.visit_array_expr(elements),
+            Expr::Assign { id, name, value } => visitor.visit_assign_expr(*id, name, value),
             Expr::Binary {
                 left,
                 operator,
                 right,
             } => visitor.visit_binary_expr(left, operator, right),
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => visitor.visit_call_expr(callee, paren, arguments),
+            Expr::CompoundAssign {
+                target,
+                operator,
+                value,
+            } => visitor.visit_compound_assign_expr(target, operator, value),
+            Expr::Get { object, name } => visitor.visit_get_expr(object, name),
             Expr::Grouping { expression } => visitor.visit_grouping_expr(expression),
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => visitor.visit_index_expr(object, bracket, index),
+            Expr::IndexAssign {
+                object,
+                bracket,
+                index,
+                value,
+            } => visitor.visit_index_assign_expr(object, bracket, index, value),
             Expr::Literal { value } => visitor.visit_literal_expr(value),
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => visitor.visit_logical_expr(left, operator, right),
+            Expr::Map { brace, entries } => visitor.visit_map_expr(brace, entries),
+            Expr::Postfix { target, operator } => visitor.visit_postfix_expr(target, operator),
+            Expr::Set { object, name, value } => visitor.visit_set_expr(object, name, value),
+            Expr::Super { id, keyword, method } => visitor.visit_super_expr(*id, keyword, method),
+            Expr::This { id, keyword } => visitor.visit_this_expr(*id, keyword),
             Expr::Unary { operator, right } => visitor.visit_unary_expr(operator, right),
+            Expr::Variable { id, name } => visitor.visit_variable_expr(*id, name),
+        }
+    }
+}
+
+impl Stmt {
+    /// Based on statement type, call the appropriate visitor method
+    pub fn accept<R>(&self, visitor: &mut dyn StmtVisitor<R>) -> CblResult<R> {
+        match self {
+            Stmt::Assert {
+                keyword,
+                condition,
+                message,
+            } => visitor.visit_assert_stmt(keyword, condition, message),
+            Stmt::Block { statements, .. } => visitor.visit_block_stmt(statements),
+            Stmt::Break { keyword } => visitor.visit_break_stmt(keyword),
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => visitor.visit_class_stmt(name, superclass, methods),
+            Stmt::Const { name, initializer } => visitor.visit_const_stmt(name, initializer),
+            Stmt::Continue { keyword } => visitor.visit_continue_stmt(keyword),
+            Stmt::Expression { expression, .. } => visitor.visit_expression_stmt(expression),
+            Stmt::ForEach { name, iterable, body } => visitor.visit_for_each_stmt(name, iterable, body),
+            Stmt::Function { name, params, body } => visitor.visit_function_stmt(name, params, body),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => visitor.visit_if_stmt(condition, then_branch, else_branch),
+            Stmt::Print { expression, .. } => visitor.visit_print_stmt(expression),
+            Stmt::Return { keyword, value } => visitor.visit_return_stmt(keyword, value),
+            Stmt::Var { name, initializer } => visitor.visit_var_stmt(name, initializer),
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => visitor.visit_while_stmt(condition, body, increment),
+        }
+    }
+
+    /// The line the statement starts on, eg. the line of the `print`
+    /// keyword or of the first token of an expression statement
+    pub fn line(&self) -> u32 {
+        match self {
+            Stmt::Assert { keyword, .. }
+            | Stmt::Break { keyword }
+            | Stmt::Continue { keyword }
+            | Stmt::Return { keyword, .. } => keyword.line,
+            Stmt::Class { name, .. }
+            | Stmt::Const { name, .. }
+            | Stmt::ForEach { name, .. }
+            | Stmt::Function { name, .. }
+            | Stmt::Var { name, .. } => name.line,
+            Stmt::Block { line, .. }
+            | Stmt::Expression { line, .. }
+            | Stmt::If { line, .. }
+            | Stmt::Print { line, .. }
+            | Stmt::While { line, .. } => *line,
         }
     }
 }
@@ -42,69 +317,977 @@ impl Expr {
 pub struct AstPrinter;
 
 impl AstPrinter {
-    pub fn print(&self, expr: Expr) -> CblResult<String> {
+    pub fn print(&mut self, expr: Expr) -> CblResult<String> {
         expr.accept(self)
     }
 
-    fn parenthesize(&self, name: String, exprs: Vec<&Expr>) -> CblResult<String> {
+    /// Print a whole program, one statement per line
+    pub fn print_stmts(&mut self, stmts: &[Stmt]) -> CblResult<String> {
+        let lines = stmts
+            .iter()
+            .map(|stmt| stmt.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        Ok(lines.join("\n"))
+    }
+
+    /// Like `parenthesize` but for a mix of already printed parts
+    fn parenthesize_parts(&self, name: &str, parts: Vec<String>) -> String {
         let mut r = String::new();
-        r.push_str("(");
+        r.push('(');
+        r.push_str(name);
+        for part in parts {
+            r.push(' ');
+            r.push_str(&part);
+        }
+        r.push(')');
+        r
+    }
+
+    fn parenthesize(&mut self, name: String, exprs: Vec<&Expr>) -> CblResult<String> {
+        let mut r = String::new();
+        r.push('(');
         r.push_str(&name);
         for e in &exprs {
-            r.push_str(" ");
-            match e.accept(self) {
-                Ok(s) => r.push_str(&s),
-                Err(e) => return Err(Error::parser_error(&format!("Error: {:?}", e))),
-            }
+            r.push(' ');
+            r.push_str(&e.accept(self)?);
         }
-        r.push_str(")");
+        r.push(')');
         Ok(r)
     }
 }
 
 impl Visitor<String> for AstPrinter {
-    fn visit_binary_expr(&self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<String> {
+    fn visit_array_expr(&mut self, elements: &[Expr]) -> CblResult<String> {
+        self.parenthesize("array".to_string(), elements.iter().collect())
+    }
+
+    fn visit_assign_expr(&mut self, _id: usize, name: &Token, value: &Expr) -> CblResult<String> {
+        self.parenthesize(format!("= {}", name.lexeme), vec![value])
+    }
+
+    fn visit_binary_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<String> {
         self.parenthesize(operator.lexeme.clone(), vec![left, right])
     }
 
-    fn visit_grouping_expr(&self, expr: &Expr) -> CblResult<String> {
+    fn visit_call_expr(&mut self, callee: &Expr, _paren: &Token, arguments: &[Expr]) -> CblResult<String> {
+        let mut exprs = vec![callee];
+        exprs.extend(arguments);
+        self.parenthesize("call".to_string(), exprs)
+    }
+
+    fn visit_compound_assign_expr(&mut self, target: &Expr, operator: &Token, value: &Expr) -> CblResult<String> {
+        self.parenthesize(format!("{}=", operator.lexeme), vec![target, value])
+    }
+
+    fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> CblResult<String> {
+        Ok(format!("(. {} {})", object.accept(self)?, name.lexeme))
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &Expr) -> CblResult<String> {
         self.parenthesize("group".to_string(), vec![expr])
     }
 
-    fn visit_literal_expr(&self, value: &Object) -> CblResult<String> {
+    fn visit_index_expr(&mut self, object: &Expr, _bracket: &Token, index: &Expr) -> CblResult<String> {
+        self.parenthesize("index".to_string(), vec![object, index])
+    }
+
+    fn visit_index_assign_expr(&mut self, object: &Expr, bracket: &Token, index: &Expr, value: &Expr) -> CblResult<String> {
+        let parts = vec![self.visit_index_expr(object, bracket, index)?, value.accept(self)?];
+        Ok(self.parenthesize_parts("=", parts))
+    }
+
+    fn visit_literal_expr(&mut self, value: &Object) -> CblResult<String> {
         Ok(value.to_string()) // check for null
     }
 
-    fn visit_unary_expr(&self, operator: &Token, right: &Expr) -> CblResult<String> {
+    fn visit_logical_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<String> {
+        self.parenthesize(operator.lexeme.clone(), vec![left, right])
+    }
+
+    fn visit_map_expr(&mut self, _brace: &Token, entries: &[(Expr, Expr)]) -> CblResult<String> {
+        self.parenthesize("map".to_string(), entries.iter().flat_map(|(key, value)| [key, value]).collect())
+    }
+
+    fn visit_postfix_expr(&mut self, target: &Expr, operator: &Token) -> CblResult<String> {
+        self.parenthesize(format!("post{}", operator.lexeme), vec![target])
+    }
+
+    fn visit_set_expr(&mut self, object: &Expr, name: &Token, value: &Expr) -> CblResult<String> {
+        let parts = vec![self.visit_get_expr(object, name)?, value.accept(self)?];
+        Ok(self.parenthesize_parts("=", parts))
+    }
+
+    fn visit_super_expr(&mut self, _id: usize, _keyword: &Token, method: &str) -> CblResult<String> {
+        Ok(format!("(. super {})", method))
+    }
+
+    fn visit_this_expr(&mut self, _id: usize, _keyword: &Token) -> CblResult<String> {
+        Ok("this".to_string())
+    }
+
+    fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> CblResult<String> {
         self.parenthesize(operator.lexeme.clone(), vec![right])
     }
+
+    fn visit_variable_expr(&mut self, _id: usize, name: &Token) -> CblResult<String> {
+        Ok(name.lexeme.clone())
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter {
+    fn visit_assert_stmt(&mut self, _keyword: &Token, condition: &Expr, message: &Option<Expr>) -> CblResult<String> {
+        match message {
+            Some(message) => self.parenthesize("assert".to_string(), vec![condition, message]),
+            None => self.parenthesize("assert".to_string(), vec![condition]),
+        }
+    }
+
+    fn visit_block_stmt(&mut self, statements: &[Stmt]) -> CblResult<String> {
+        let parts = statements
+            .iter()
+            .map(|stmt| stmt.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        Ok(self.parenthesize_parts("block", parts))
+    }
+
+    fn visit_break_stmt(&mut self, _keyword: &Token) -> CblResult<String> {
+        Ok(self.parenthesize_parts("break", vec![]))
+    }
+
+    fn visit_class_stmt(&mut self, name: &Token, superclass: &Option<Expr>, methods: &[Stmt]) -> CblResult<String> {
+        let parts = methods
+            .iter()
+            .map(|method| method.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        let label = match superclass {
+            Some(superclass) => format!("class {} < {}", name.lexeme, superclass.accept(self)?),
+            None => format!("class {}", name.lexeme),
+        };
+        Ok(self.parenthesize_parts(&label, parts))
+    }
+
+    fn visit_const_stmt(&mut self, name: &Token, initializer: &Expr) -> CblResult<String> {
+        self.parenthesize(format!("const {}", name.lexeme), vec![initializer])
+    }
+
+    fn visit_continue_stmt(&mut self, _keyword: &Token) -> CblResult<String> {
+        Ok(self.parenthesize_parts("continue", vec![]))
+    }
+
+    fn visit_expression_stmt(&mut self, expression: &Expr) -> CblResult<String> {
+        self.parenthesize(";".to_string(), vec![expression])
+    }
+
+    fn visit_for_each_stmt(&mut self, name: &Token, iterable: &Expr, body: &Stmt) -> CblResult<String> {
+        let parts = vec![iterable.accept(self)?, body.accept(self)?];
+        Ok(self.parenthesize_parts(&format!("for {} in", name.lexeme), parts))
+    }
+
+    fn visit_function_stmt(&mut self, name: &Token, params: &[Token], body: &[Stmt]) -> CblResult<String> {
+        let params = params.iter().map(|param| param.lexeme.clone()).collect::<Vec<String>>();
+        let mut parts = vec![format!("({})", params.join(" "))];
+        for stmt in body {
+            parts.push(stmt.accept(self)?);
+        }
+        Ok(self.parenthesize_parts(&format!("fun {}", name.lexeme), parts))
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> CblResult<String> {
+        let mut parts = vec![condition.accept(self)?, then_branch.accept(self)?];
+        match else_branch {
+            Some(else_branch) => {
+                parts.push(else_branch.accept(self)?);
+                Ok(self.parenthesize_parts("if-else", parts))
+            }
+            None => Ok(self.parenthesize_parts("if", parts)),
+        }
+    }
+
+    fn visit_print_stmt(&mut self, expression: &Expr) -> CblResult<String> {
+        self.parenthesize("print".to_string(), vec![expression])
+    }
+
+    fn visit_return_stmt(&mut self, _keyword: &Token, value: &Option<Expr>) -> CblResult<String> {
+        match value {
+            Some(value) => self.parenthesize("return".to_string(), vec![value]),
+            None => Ok(self.parenthesize_parts("return", vec![])),
+        }
+    }
+
+    fn visit_var_stmt(&mut self, name: &Token, initializer: &Option<Expr>) -> CblResult<String> {
+        let name = format!("var {}", name.lexeme);
+        match initializer {
+            Some(initializer) => self.parenthesize(name, vec![initializer]),
+            None => Ok(self.parenthesize_parts(&name, vec![])),
+        }
+    }
+
+    fn visit_while_stmt(&mut self, condition: &Expr, body: &Stmt, increment: &Option<Expr>) -> CblResult<String> {
+        let mut parts = vec![condition.accept(self)?, body.accept(self)?];
+        if let Some(increment) = increment {
+            parts.push(increment.accept(self)?);
+        }
+        Ok(self.parenthesize_parts("while", parts))
+    }
+}
+
+/// Prints a program as an indented tree, one node per line with its
+/// children two spaces further in, eg. "1 + 2;" becomes
+///
+/// ```text
+/// Expression
+///   Binary +
+///     Literal 1
+///     Literal 2
+/// ```
+pub struct TreePrinter;
+
+impl TreePrinter {
+    pub fn print(&mut self, expr: &Expr) -> CblResult<String> {
+        expr.accept(self)
+    }
+
+    /// Print a whole program, statements follow each other at the top level
+    pub fn print_stmts(&mut self, stmts: &[Stmt]) -> CblResult<String> {
+        let trees = stmts
+            .iter()
+            .map(|stmt| stmt.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        Ok(trees.join("\n"))
+    }
+
+    /// A node labelled `label` with the already printed `children` under it
+    fn node(&self, label: &str, children: Vec<String>) -> String {
+        let mut r = label.to_string();
+        for child in children {
+            for line in child.lines() {
+                r.push_str("\n  ");
+                r.push_str(line);
+            }
+        }
+        r
+    }
+
+    fn node_exprs(&mut self, label: &str, exprs: Vec<&Expr>) -> CblResult<String> {
+        let children = exprs
+            .iter()
+            .map(|expr| expr.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        Ok(self.node(label, children))
+    }
+}
+
+impl Visitor<String> for TreePrinter {
+    fn visit_array_expr(&mut self, elements: &[Expr]) -> CblResult<String> {
+        self.node_exprs("Array", elements.iter().collect())
+    }
+
+    fn visit_assign_expr(&mut self, _id: usize, name: &Token, value: &Expr) -> CblResult<String> {
+        self.node_exprs(&format!("Assign {}", name.lexeme), vec![value])
+    }
+
+    fn visit_binary_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<String> {
+        self.node_exprs(&format!("Binary {}", operator.lexeme), vec![left, right])
+    }
+
+    fn visit_call_expr(&mut self, callee: &Expr, _paren: &Token, arguments: &[Expr]) -> CblResult<String> {
+        let mut exprs = vec![callee];
+        exprs.extend(arguments);
+        self.node_exprs("Call", exprs)
+    }
+
+    fn visit_compound_assign_expr(&mut self, target: &Expr, operator: &Token, value: &Expr) -> CblResult<String> {
+        self.node_exprs(&format!("CompoundAssign {}=", operator.lexeme), vec![target, value])
+    }
+
+    fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> CblResult<String> {
+        self.node_exprs(&format!("Get {}", name.lexeme), vec![object])
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &Expr) -> CblResult<String> {
+        self.node_exprs("Grouping", vec![expr])
+    }
+
+    fn visit_index_expr(&mut self, object: &Expr, _bracket: &Token, index: &Expr) -> CblResult<String> {
+        self.node_exprs("Index", vec![object, index])
+    }
+
+    fn visit_index_assign_expr(&mut self, object: &Expr, _bracket: &Token, index: &Expr, value: &Expr) -> CblResult<String> {
+        self.node_exprs("IndexAssign", vec![object, index, value])
+    }
+
+    fn visit_literal_expr(&mut self, value: &Object) -> CblResult<String> {
+        let value = match value {
+            Object::String(s) => json_string(s),
+            value => value.to_string(),
+        };
+        Ok(format!("Literal {}", value))
+    }
+
+    fn visit_logical_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<String> {
+        self.node_exprs(&format!("Logical {}", operator.lexeme), vec![left, right])
+    }
+
+    fn visit_map_expr(&mut self, _brace: &Token, entries: &[(Expr, Expr)]) -> CblResult<String> {
+        let children = entries
+            .iter()
+            .map(|(key, value)| self.node_exprs("Entry", vec![key, value]))
+            .collect::<CblResult<Vec<String>>>()?;
+        Ok(self.node("Map", children))
+    }
+
+    fn visit_postfix_expr(&mut self, target: &Expr, operator: &Token) -> CblResult<String> {
+        self.node_exprs(&format!("Postfix {}", operator.lexeme), vec![target])
+    }
+
+    fn visit_set_expr(&mut self, object: &Expr, name: &Token, value: &Expr) -> CblResult<String> {
+        self.node_exprs(&format!("Set {}", name.lexeme), vec![object, value])
+    }
+
+    fn visit_super_expr(&mut self, _id: usize, _keyword: &Token, method: &str) -> CblResult<String> {
+        Ok(format!("Super {}", method))
+    }
+
+    fn visit_this_expr(&mut self, _id: usize, _keyword: &Token) -> CblResult<String> {
+        Ok("This".to_string())
+    }
+
+    fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> CblResult<String> {
+        self.node_exprs(&format!("Unary {}", operator.lexeme), vec![right])
+    }
+
+    fn visit_variable_expr(&mut self, _id: usize, name: &Token) -> CblResult<String> {
+        Ok(format!("Variable {}", name.lexeme))
+    }
+}
+
+impl StmtVisitor<String> for TreePrinter {
+    fn visit_assert_stmt(&mut self, _keyword: &Token, condition: &Expr, message: &Option<Expr>) -> CblResult<String> {
+        match message {
+            Some(message) => self.node_exprs("Assert", vec![condition, message]),
+            None => self.node_exprs("Assert", vec![condition]),
+        }
+    }
+
+    fn visit_block_stmt(&mut self, statements: &[Stmt]) -> CblResult<String> {
+        let children = statements
+            .iter()
+            .map(|stmt| stmt.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        Ok(self.node("Block", children))
+    }
+
+    fn visit_break_stmt(&mut self, _keyword: &Token) -> CblResult<String> {
+        Ok("Break".to_string())
+    }
+
+    fn visit_class_stmt(&mut self, name: &Token, superclass: &Option<Expr>, methods: &[Stmt]) -> CblResult<String> {
+        let children = methods
+            .iter()
+            .map(|method| method.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        let label = match superclass {
+            Some(Expr::Variable { name: superclass, .. }) => format!("Class {} < {}", name.lexeme, superclass.lexeme),
+            _ => format!("Class {}", name.lexeme),
+        };
+        Ok(self.node(&label, children))
+    }
+
+    fn visit_const_stmt(&mut self, name: &Token, initializer: &Expr) -> CblResult<String> {
+        self.node_exprs(&format!("Const {}", name.lexeme), vec![initializer])
+    }
+
+    fn visit_continue_stmt(&mut self, _keyword: &Token) -> CblResult<String> {
+        Ok("Continue".to_string())
+    }
+
+    fn visit_expression_stmt(&mut self, expression: &Expr) -> CblResult<String> {
+        self.node_exprs("Expression", vec![expression])
+    }
+
+    fn visit_for_each_stmt(&mut self, name: &Token, iterable: &Expr, body: &Stmt) -> CblResult<String> {
+        let children = vec![iterable.accept(self)?, body.accept(self)?];
+        Ok(self.node(&format!("ForEach {}", name.lexeme), children))
+    }
+
+    fn visit_function_stmt(&mut self, name: &Token, params: &[Token], body: &[Stmt]) -> CblResult<String> {
+        let params = params.iter().map(|param| param.lexeme.clone()).collect::<Vec<String>>();
+        let children = body
+            .iter()
+            .map(|stmt| stmt.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        Ok(self.node(&format!("Function {}({})", name.lexeme, params.join(", ")), children))
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> CblResult<String> {
+        let mut children = vec![condition.accept(self)?, then_branch.accept(self)?];
+        if let Some(else_branch) = else_branch {
+            let else_branch = else_branch.accept(self)?;
+            children.push(self.node("Else", vec![else_branch]));
+        }
+        Ok(self.node("If", children))
+    }
+
+    fn visit_print_stmt(&mut self, expression: &Expr) -> CblResult<String> {
+        self.node_exprs("Print", vec![expression])
+    }
+
+    fn visit_return_stmt(&mut self, _keyword: &Token, value: &Option<Expr>) -> CblResult<String> {
+        self.node_exprs("Return", value.iter().collect())
+    }
+
+    fn visit_var_stmt(&mut self, name: &Token, initializer: &Option<Expr>) -> CblResult<String> {
+        self.node_exprs(&format!("Var {}", name.lexeme), initializer.iter().collect())
+    }
+
+    fn visit_while_stmt(&mut self, condition: &Expr, body: &Stmt, increment: &Option<Expr>) -> CblResult<String> {
+        let mut children = vec![condition.accept(self)?, body.accept(self)?];
+        if let Some(increment) = increment {
+            children.push(self.node_exprs("Increment", vec![increment])?);
+        }
+        Ok(self.node("While", children))
+    }
+}
+
+/// Prints expressions in Reverse Polish Notation, eg. "(1 + 2) * 3"
+/// becomes "1 2 + 3 *". Unary minus is written as "neg" so it can't be
+/// confused with subtraction
+pub struct RpnPrinter;
+
+impl RpnPrinter {
+    pub fn print(&mut self, expr: Expr) -> CblResult<String> {
+        expr.accept(self)
+    }
+}
+
+impl Visitor<String> for RpnPrinter {
+    fn visit_array_expr(&mut self, elements: &[Expr]) -> CblResult<String> {
+        let mut parts = elements
+            .iter()
+            .map(|element| element.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        parts.push(format!("array{}", elements.len()));
+        Ok(parts.join(" "))
+    }
+
+    fn visit_assign_expr(&mut self, _id: usize, name: &Token, value: &Expr) -> CblResult<String> {
+        Ok(format!("{} {} =", value.accept(self)?, name.lexeme))
+    }
+
+    fn visit_binary_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<String> {
+        Ok(format!("{} {} {}", left.accept(self)?, right.accept(self)?, operator.lexeme))
+    }
+
+    fn visit_call_expr(&mut self, callee: &Expr, _paren: &Token, arguments: &[Expr]) -> CblResult<String> {
+        let mut parts = vec![callee.accept(self)?];
+        for argument in arguments {
+            parts.push(argument.accept(self)?);
+        }
+        parts.push("call".to_string());
+        Ok(parts.join(" "))
+    }
+
+    fn visit_compound_assign_expr(&mut self, target: &Expr, operator: &Token, value: &Expr) -> CblResult<String> {
+        Ok(format!("{} {} {}=", target.accept(self)?, value.accept(self)?, operator.lexeme))
+    }
+
+    fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> CblResult<String> {
+        Ok(format!("{} .{}", object.accept(self)?, name.lexeme))
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &Expr) -> CblResult<String> {
+        expr.accept(self)
+    }
+
+    fn visit_index_expr(&mut self, object: &Expr, _bracket: &Token, index: &Expr) -> CblResult<String> {
+        Ok(format!("{} {} index", object.accept(self)?, index.accept(self)?))
+    }
+
+    fn visit_index_assign_expr(&mut self, object: &Expr, _bracket: &Token, index: &Expr, value: &Expr) -> CblResult<String> {
+        Ok(format!("{} {} {} index=", object.accept(self)?, index.accept(self)?, value.accept(self)?))
+    }
+
+    fn visit_literal_expr(&mut self, value: &Object) -> CblResult<String> {
+        Ok(value.to_string())
+    }
+
+    fn visit_logical_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<String> {
+        Ok(format!("{} {} {}", left.accept(self)?, right.accept(self)?, operator.lexeme))
+    }
+
+    fn visit_map_expr(&mut self, _brace: &Token, entries: &[(Expr, Expr)]) -> CblResult<String> {
+        let mut parts = Vec::new();
+        for (key, value) in entries {
+            parts.push(key.accept(self)?);
+            parts.push(value.accept(self)?);
+        }
+        parts.push(format!("map{}", entries.len()));
+        Ok(parts.join(" "))
+    }
+
+    fn visit_postfix_expr(&mut self, target: &Expr, operator: &Token) -> CblResult<String> {
+        Ok(format!("{} {}", target.accept(self)?, operator.lexeme))
+    }
+
+    fn visit_set_expr(&mut self, object: &Expr, name: &Token, value: &Expr) -> CblResult<String> {
+        Ok(format!("{} {} .{}=", object.accept(self)?, value.accept(self)?, name.lexeme))
+    }
+
+    fn visit_super_expr(&mut self, _id: usize, _keyword: &Token, method: &str) -> CblResult<String> {
+        Ok(format!("super .{}", method))
+    }
+
+    fn visit_this_expr(&mut self, _id: usize, _keyword: &Token) -> CblResult<String> {
+        Ok("this".to_string())
+    }
+
+    fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> CblResult<String> {
+        let name = match operator.type_ {
+            TokenType::Minus => "neg",
+            _ => &operator.lexeme,
+        };
+        Ok(format!("{} {}", right.accept(self)?, name))
+    }
+
+    fn visit_variable_expr(&mut self, _id: usize, name: &Token) -> CblResult<String> {
+        Ok(name.lexeme.clone())
+    }
+}
+
+/// Serialize a whole program to a JSON array with one object per
+/// statement, eg. "1 * 2;" becomes
+/// [{"type":"Expression","expression":{"type":"Binary","operator":"*",...}}]
+pub fn ast_to_json(stmts: &[Stmt]) -> CblResult<String> {
+    let parts = stmts
+        .iter()
+        .map(|stmt| stmt.accept(&mut JsonPrinter))
+        .collect::<CblResult<Vec<String>>>()?;
+    Ok(format!("[{}]", parts.join(",")))
+}
+
+/// Builds the JSON for a single node, see `ast_to_json`
+pub struct JsonPrinter;
+
+/// Join already serialized `fields` into an object tagged with `type_`
+fn json_object(type_: &str, fields: Vec<(&str, String)>) -> String {
+    let mut r = format!("{{\"type\":{}", json_string(type_));
+    for (key, value) in fields {
+        r.push_str(&format!(",{}:{}", json_string(key), value));
+    }
+    r.push('}');
+    r
+}
+
+impl Visitor<String> for JsonPrinter {
+    fn visit_array_expr(&mut self, elements: &[Expr]) -> CblResult<String> {
+        let elements = elements
+            .iter()
+            .map(|element| element.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        Ok(json_object("Array", vec![("elements", format!("[{}]", elements.join(",")))]))
+    }
+
+    fn visit_assign_expr(&mut self, _id: usize, name: &Token, value: &Expr) -> CblResult<String> {
+        Ok(json_object("Assign", vec![
+            ("name", json_string(&name.lexeme)),
+            ("value", value.accept(self)?),
+        ]))
+    }
+
+    fn visit_binary_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<String> {
+        Ok(json_object("Binary", vec![
+            ("operator", json_string(&operator.lexeme)),
+            ("left", left.accept(self)?),
+            ("right", right.accept(self)?),
+        ]))
+    }
+
+    fn visit_call_expr(&mut self, callee: &Expr, _paren: &Token, arguments: &[Expr]) -> CblResult<String> {
+        let arguments = arguments
+            .iter()
+            .map(|argument| argument.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        Ok(json_object("Call", vec![
+            ("callee", callee.accept(self)?),
+            ("arguments", format!("[{}]", arguments.join(","))),
+        ]))
+    }
+
+    fn visit_compound_assign_expr(&mut self, target: &Expr, operator: &Token, value: &Expr) -> CblResult<String> {
+        Ok(json_object("CompoundAssign", vec![
+            ("operator", json_string(&format!("{}=", operator.lexeme))),
+            ("target", target.accept(self)?),
+            ("value", value.accept(self)?),
+        ]))
+    }
+
+    fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> CblResult<String> {
+        Ok(json_object("Get", vec![
+            ("object", object.accept(self)?),
+            ("name", json_string(&name.lexeme)),
+        ]))
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &Expr) -> CblResult<String> {
+        Ok(json_object("Grouping", vec![("expression", expr.accept(self)?)]))
+    }
+
+    fn visit_index_expr(&mut self, object: &Expr, _bracket: &Token, index: &Expr) -> CblResult<String> {
+        Ok(json_object("Index", vec![
+            ("object", object.accept(self)?),
+            ("index", index.accept(self)?),
+        ]))
+    }
+
+    fn visit_index_assign_expr(&mut self, object: &Expr, _bracket: &Token, index: &Expr, value: &Expr) -> CblResult<String> {
+        Ok(json_object("IndexAssign", vec![
+            ("object", object.accept(self)?),
+            ("index", index.accept(self)?),
+            ("value", value.accept(self)?),
+        ]))
+    }
+
+    fn visit_literal_expr(&mut self, value: &Object) -> CblResult<String> {
+        Ok(json_object("Literal", vec![
+            ("kind", json_string(value.kind())),
+            ("value", value.to_json()),
+        ]))
+    }
+
+    fn visit_logical_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<String> {
+        Ok(json_object("Logical", vec![
+            ("operator", json_string(&operator.lexeme)),
+            ("left", left.accept(self)?),
+            ("right", right.accept(self)?),
+        ]))
+    }
+
+    fn visit_map_expr(&mut self, _brace: &Token, entries: &[(Expr, Expr)]) -> CblResult<String> {
+        let entries = entries
+            .iter()
+            .map(|(key, value)| Ok(format!("{{\"key\":{},\"value\":{}}}", key.accept(self)?, value.accept(self)?)))
+            .collect::<CblResult<Vec<String>>>()?;
+        Ok(json_object("Map", vec![("entries", format!("[{}]", entries.join(",")))]))
+    }
+
+    fn visit_postfix_expr(&mut self, target: &Expr, operator: &Token) -> CblResult<String> {
+        Ok(json_object("Postfix", vec![
+            ("operator", json_string(&operator.lexeme)),
+            ("target", target.accept(self)?),
+        ]))
+    }
+
+    fn visit_set_expr(&mut self, object: &Expr, name: &Token, value: &Expr) -> CblResult<String> {
+        Ok(json_object("Set", vec![
+            ("object", object.accept(self)?),
+            ("name", json_string(&name.lexeme)),
+            ("value", value.accept(self)?),
+        ]))
+    }
+
+    fn visit_super_expr(&mut self, _id: usize, _keyword: &Token, method: &str) -> CblResult<String> {
+        Ok(json_object("Super", vec![("method", json_string(method))]))
+    }
+
+    fn visit_this_expr(&mut self, _id: usize, _keyword: &Token) -> CblResult<String> {
+        Ok(json_object("This", vec![]))
+    }
+
+    fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> CblResult<String> {
+        Ok(json_object("Unary", vec![
+            ("operator", json_string(&operator.lexeme)),
+            ("right", right.accept(self)?),
+        ]))
+    }
+
+    fn visit_variable_expr(&mut self, _id: usize, name: &Token) -> CblResult<String> {
+        Ok(json_object("Variable", vec![("name", json_string(&name.lexeme))]))
+    }
+}
+
+impl StmtVisitor<String> for JsonPrinter {
+    fn visit_assert_stmt(&mut self, _keyword: &Token, condition: &Expr, message: &Option<Expr>) -> CblResult<String> {
+        let message = match message {
+            Some(message) => message.accept(self)?,
+            None => "null".to_string(),
+        };
+        Ok(json_object("Assert", vec![
+            ("condition", condition.accept(self)?),
+            ("message", message),
+        ]))
+    }
+
+    fn visit_block_stmt(&mut self, statements: &[Stmt]) -> CblResult<String> {
+        let statements = statements
+            .iter()
+            .map(|stmt| stmt.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        Ok(json_object("Block", vec![("statements", format!("[{}]", statements.join(",")))]))
+    }
+
+    fn visit_break_stmt(&mut self, _keyword: &Token) -> CblResult<String> {
+        Ok(json_object("Break", vec![]))
+    }
+
+    fn visit_class_stmt(&mut self, name: &Token, superclass: &Option<Expr>, methods: &[Stmt]) -> CblResult<String> {
+        let methods = methods
+            .iter()
+            .map(|method| method.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        let superclass = match superclass {
+            Some(superclass) => superclass.accept(self)?,
+            None => "null".to_string(),
+        };
+        Ok(json_object("Class", vec![
+            ("name", json_string(&name.lexeme)),
+            ("superclass", superclass),
+            ("methods", format!("[{}]", methods.join(","))),
+        ]))
+    }
+
+    fn visit_const_stmt(&mut self, name: &Token, initializer: &Expr) -> CblResult<String> {
+        Ok(json_object("Const", vec![
+            ("name", json_string(&name.lexeme)),
+            ("initializer", initializer.accept(self)?),
+        ]))
+    }
+
+    fn visit_continue_stmt(&mut self, _keyword: &Token) -> CblResult<String> {
+        Ok(json_object("Continue", vec![]))
+    }
+
+    fn visit_expression_stmt(&mut self, expression: &Expr) -> CblResult<String> {
+        Ok(json_object("Expression", vec![("expression", expression.accept(self)?)]))
+    }
+
+    fn visit_for_each_stmt(&mut self, name: &Token, iterable: &Expr, body: &Stmt) -> CblResult<String> {
+        Ok(json_object("ForEach", vec![
+            ("name", json_string(&name.lexeme)),
+            ("iterable", iterable.accept(self)?),
+            ("body", body.accept(self)?),
+        ]))
+    }
+
+    fn visit_function_stmt(&mut self, name: &Token, params: &[Token], body: &[Stmt]) -> CblResult<String> {
+        let params = params.iter().map(|param| json_string(&param.lexeme)).collect::<Vec<String>>();
+        let body = body
+            .iter()
+            .map(|stmt| stmt.accept(self))
+            .collect::<CblResult<Vec<String>>>()?;
+        Ok(json_object("Function", vec![
+            ("name", json_string(&name.lexeme)),
+            ("params", format!("[{}]", params.join(","))),
+            ("body", format!("[{}]", body.join(","))),
+        ]))
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> CblResult<String> {
+        let else_branch = match else_branch {
+            Some(else_branch) => else_branch.accept(self)?,
+            None => "null".to_string(),
+        };
+        Ok(json_object("If", vec![
+            ("condition", condition.accept(self)?),
+            ("then_branch", then_branch.accept(self)?),
+            ("else_branch", else_branch),
+        ]))
+    }
+
+    fn visit_print_stmt(&mut self, expression: &Expr) -> CblResult<String> {
+        Ok(json_object("Print", vec![("expression", expression.accept(self)?)]))
+    }
+
+    fn visit_return_stmt(&mut self, _keyword: &Token, value: &Option<Expr>) -> CblResult<String> {
+        let value = match value {
+            Some(value) => value.accept(self)?,
+            None => "null".to_string(),
+        };
+        Ok(json_object("Return", vec![("value", value)]))
+    }
+
+    fn visit_var_stmt(&mut self, name: &Token, initializer: &Option<Expr>) -> CblResult<String> {
+        let initializer = match initializer {
+            Some(initializer) => initializer.accept(self)?,
+            None => "null".to_string(),
+        };
+        Ok(json_object("Var", vec![
+            ("name", json_string(&name.lexeme)),
+            ("initializer", initializer),
+        ]))
+    }
+
+    fn visit_while_stmt(&mut self, condition: &Expr, body: &Stmt, increment: &Option<Expr>) -> CblResult<String> {
+        let increment = match increment {
+            Some(increment) => increment.accept(self)?,
+            None => "null".to_string(),
+        };
+        Ok(json_object("While", vec![
+            ("condition", condition.accept(self)?),
+            ("body", body.accept(self)?),
+            ("increment", increment),
+        ]))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::token::{Object, TokenType};
-
     use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
 
     #[test]
     fn test_ast_printer() {
         let expression = Expr::Binary {
             left: Box::new(Expr::Unary {
-                operator: Token::new(TokenType::Minus, "-".to_string(), Object::Nil, 1),
+                operator: Token::new(TokenType::Minus, "-".to_string(), Object::Nil, 1, 1),
                 right: Box::new(Expr::Literal {
                     value: Object::Number(123_f64),
                 }),
             }),
-            operator: Token::new(TokenType::Star, "*".to_string(), Object::Nil, 1),
+            operator: Token::new(TokenType::Star, "*".to_string(), Object::Nil, 1, 1),
             right: Box::new(Expr::Grouping {
                 expression: Box::new(Expr::Literal {
                     value: Object::Number(45.67_f64),
                 }),
             }),
         };
-        let printer = AstPrinter;
+        let mut printer = AstPrinter;
         let result = printer.print(expression).unwrap();
 
         assert_eq!(result, "(* (- 123) (group 45.67))");
     }
+
+    #[test]
+    fn test_rpn_printer() {
+        let expression = Expr::Binary {
+            left: Box::new(Expr::Unary {
+                operator: Token::new(TokenType::Minus, "-".to_string(), Object::Nil, 1, 1),
+                right: Box::new(Expr::Literal {
+                    value: Object::Number(123_f64),
+                }),
+            }),
+            operator: Token::new(TokenType::Star, "*".to_string(), Object::Nil, 1, 1),
+            right: Box::new(Expr::Grouping {
+                expression: Box::new(Expr::Literal {
+                    value: Object::Number(45.67_f64),
+                }),
+            }),
+        };
+        let mut printer = RpnPrinter;
+        let result = printer.print(expression).unwrap();
+
+        assert_eq!(result, "123 neg 45.67 *");
+    }
+
+    #[test]
+    fn test_rpn_printer_grouping() {
+        let mut scanner = Scanner::new("(1 + 2) * 3;");
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut statements = Parser::new(tokens).parse().unwrap();
+
+        let expression = match statements.remove(0) {
+            Stmt::Expression { expression, .. } => expression,
+            _ => panic!("Expected an expression statement."),
+        };
+        assert_eq!(RpnPrinter.print(expression).unwrap(), "1 2 + 3 *");
+    }
+
+    #[test]
+    fn test_ast_printer_statements() {
+        let mut scanner = Scanner::new("var x = 3; var y; print 1 + 2; if (x) { x = 1; } else y;");
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+
+        let mut printer = AstPrinter;
+        assert_eq!(
+            printer.print_stmts(&statements).unwrap(),
+            "(var x 3)\n(var y)\n(print (+ 1 2))\n(if-else x (block (; (= x 1))) (; y))"
+        );
+    }
+
+    #[test]
+    fn test_ast_to_json_has_one_element_per_statement() {
+        let tokens = Scanner::new("var a = [1]; { print a; }").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+
+        assert_eq!(
+            ast_to_json(&statements).unwrap(),
+            concat!(
+                r#"[{"type":"Var","name":"a","initializer":{"type":"Array","elements":[{"type":"Literal","kind":"number","value":1}]}},"#,
+                r#"{"type":"Block","statements":[{"type":"Print","expression":{"type":"Variable","name":"a"}}]}]"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_ast_to_json() {
+        let mut scanner = Scanner::new("print -2 * \"a\\\"b\"; var x;");
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+
+        assert_eq!(
+            ast_to_json(&statements).unwrap(),
+            concat!(
+                r#"[{"type":"Print","expression":{"type":"Binary","operator":"*","#,
+                r#""left":{"type":"Unary","operator":"-","right":{"type":"Literal","kind":"number","value":2}},"#,
+                r#""right":{"type":"Literal","kind":"string","value":"a\"b"}}},"#,
+                r#"{"type":"Var","name":"x","initializer":null}]"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_ast_to_json_escapes() {
+        let tokens = Scanner::new("var s = \"say \\\"hi\\\"\\n\\t\\\\\"; print {\"k\\\"ey\": s};").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+
+        // the source has a string with quotes, a newline, a tab and a
+        // backslash in it, and a map key with a quote
+        assert_eq!(
+            ast_to_json(&statements).unwrap(),
+            concat!(
+                r#"[{"type":"Var","name":"s","initializer":{"type":"Literal","kind":"string","value":"say \"hi\"\n\t\\"}},"#,
+                r#"{"type":"Print","expression":{"type":"Map","entries":[{"key":{"type":"Literal","kind":"string","value":"k\"ey"},"#,
+                r#""value":{"type":"Variable","name":"s"}}]}}]"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_tree_printer() {
+        let mut scanner = Scanner::new("1 + 2 * 3;");
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+
+        assert_eq!(
+            TreePrinter.print_stmts(&statements).unwrap(),
+            "Expression\n  Binary +\n    Literal 1\n    Binary *\n      Literal 2\n      Literal 3"
+        );
+    }
+
+    #[test]
+    fn test_tree_printer_statements() {
+        let mut scanner = Scanner::new("if (x) print \"a\"; else { var y; }");
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+
+        assert_eq!(
+            TreePrinter.print_stmts(&statements).unwrap(),
+            "If\n  Variable x\n  Print\n    Literal \"a\"\n  Else\n    Block\n      Var y"
+        );
+    }
 }
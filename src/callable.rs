@@ -0,0 +1,21 @@
+use std::fmt::Debug;
+
+use crate::{error::CblResult, interpreter::Interpreter, token::Object};
+
+/// Anything a script can call with "callee(arguments)", both natives and
+/// functions declared with `fun`. Debug is used to print the value, eg.
+/// "<fn add>"
+pub trait Callable: Debug {
+    fn name(&self) -> &str;
+
+    fn arity(&self) -> usize;
+
+    /// Whether any number of arguments past `arity` may be passed, eg.
+    /// `format`. `arity` is then the least it can be called with
+    fn is_variadic(&self) -> bool {
+        false
+    }
+
+    /// Run the callable, the caller has already checked the arity
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Object>) -> CblResult<Object>;
+}
@@ -0,0 +1,101 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Debug, rc::Rc};
+
+use crate::{
+    callable::Callable,
+    error::{CblResult, Error},
+    function::Function,
+    interpreter::Interpreter,
+    token::{Object, Token},
+};
+
+/// A class declared with "class Name < Superclass { methods }". Calling it makes a
+/// new instance and runs its `init` method, if it has one
+#[derive(Clone)]
+pub struct Class {
+    pub name: String,
+    /// The class from "class Name < Superclass", searched for methods
+    /// this class doesn't have
+    pub superclass: Option<Rc<Class>>,
+    /// Shared so that every instance can point at its class cheaply
+    pub methods: Rc<HashMap<String, Function>>,
+}
+
+impl Class {
+    pub fn new(name: &str, superclass: Option<Rc<Class>>, methods: HashMap<String, Function>) -> Self {
+        Class {
+            name: name.to_string(),
+            superclass,
+            methods: Rc::new(methods),
+        }
+    }
+
+    pub fn find_method(&self, name: &str) -> Option<&Function> {
+        self.methods
+            .get(name)
+            .or_else(|| self.superclass.as_ref()?.find_method(name))
+    }
+}
+
+impl Callable for Class {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// A class takes the same arguments as its `init` method
+    fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, Callable::arity)
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Object>) -> CblResult<Object> {
+        let instance = Object::Instance(Rc::new(Instance::new(self.clone())));
+        if let Some(init) = self.find_method("init") {
+            init.bind(instance.clone()).call(interpreter, arguments)?;
+        }
+        Ok(instance)
+    }
+}
+
+impl Debug for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+/// An object made by calling a class. Fields are created by assigning
+/// to them, eg. "point.x = 1"
+pub struct Instance {
+    pub class: Class,
+    pub fields: RefCell<HashMap<String, Object>>,
+}
+
+impl Instance {
+    pub fn new(class: Class) -> Self {
+        Instance {
+            class,
+            fields: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Look up `name` on `instance`, fields first and then methods of
+    /// its class with `this` bound to the instance
+    pub fn get(instance: &Rc<Instance>, name: &Token) -> CblResult<Object> {
+        if let Some(value) = instance.fields.borrow().get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+
+        match instance.class.find_method(&name.lexeme) {
+            Some(method) => Ok(Object::Callable(Rc::new(method.bind(Object::Instance(instance.clone()))))),
+            None => Err(Error::runtime_error_at(name, &format!("Undefined property '{}'.", name.lexeme))),
+        }
+    }
+
+    pub fn set(&self, name: &Token, value: Object) {
+        self.fields.borrow_mut().insert(name.lexeme.clone(), value);
+    }
+}
+
+impl Debug for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{} instance>", self.class.name)
+    }
+}
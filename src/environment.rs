@@ -0,0 +1,145 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::{
+    error::{CblResult, Error},
+    token::{Object, Token},
+};
+
+/// Storage for the variables bound while a program runs. Each block
+/// gets its own environment which points back at the one enclosing it
+#[derive(Default)]
+pub struct Environment {
+    enclosing: Option<Rc<RefCell<Environment>>>,
+    values: HashMap<String, Object>,
+    /// Names in `values` declared with `const`, which can't be assigned
+    constants: HashSet<String>,
+}
+
+/// A copy of the variables in one scope, see `Interpreter::snapshot`
+#[derive(Debug, Clone)]
+pub struct EnvSnapshot {
+    values: HashMap<String, Object>,
+    constants: HashSet<String>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment {
+            enclosing: None,
+            values: HashMap::new(),
+            constants: HashSet::new(),
+        }
+    }
+
+    /// Create a new (inner) scope nested inside of `enclosing`
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            enclosing: Some(enclosing),
+            values: HashMap::new(),
+            constants: HashSet::new(),
+        }
+    }
+
+    /// Bind a name to a value. Redefining an existing name simply
+    /// overwrites the old value
+    pub fn define(&mut self, name: &str, value: Object) {
+        self.constants.remove(name);
+        self.values.insert(name.to_string(), value);
+    }
+
+    /// Like `define`, but later assignments to the name are an error
+    pub fn define_constant(&mut self, name: &str, value: Object) {
+        self.values.insert(name.to_string(), value);
+        self.constants.insert(name.to_string());
+    }
+
+    /// Look up the value currently bound to a name, walking outwards
+    /// through the enclosing scopes until it is found
+    pub fn get(&self, name: &Token) -> CblResult<Object> {
+        if let Some(value) = self.values.get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get(name),
+            None => Err(undefined(name)),
+        }
+    }
+
+    /// Update the value of a name that has already been defined, in
+    /// whichever scope it was declared
+    pub fn assign(&mut self, name: &Token, value: Object) -> CblResult<()> {
+        if self.constants.contains(&name.lexeme) {
+            return Err(constant(name));
+        }
+        if let Some(slot) = self.values.get_mut(&name.lexeme) {
+            *slot = value;
+            return Ok(());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => Err(undefined(name)),
+        }
+    }
+
+    /// Copy the variables bound in this scope, not the enclosing ones
+    pub fn snapshot(&self) -> EnvSnapshot {
+        EnvSnapshot {
+            values: self.values.clone(),
+            constants: self.constants.clone(),
+        }
+    }
+
+    /// Put back the variables from `snapshot`, dropping any defined since
+    pub fn restore(&mut self, snapshot: EnvSnapshot) {
+        self.values = snapshot.values;
+        self.constants = snapshot.constants;
+    }
+
+    /// Look up a name exactly `distance` scopes out from this one, as
+    /// worked out by the resolver
+    pub fn get_at(&self, distance: usize, name: &Token) -> CblResult<Object> {
+        if distance == 0 {
+            return self.values.get(&name.lexeme).cloned().ok_or_else(|| undefined(name));
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get_at(distance - 1, name),
+            None => Err(undefined(name)),
+        }
+    }
+
+    /// Like `get_at` but assigns a new value
+    pub fn assign_at(&mut self, distance: usize, name: &Token, value: Object) -> CblResult<()> {
+        if distance == 0 {
+            if self.constants.contains(&name.lexeme) {
+                return Err(constant(name));
+            }
+            return match self.values.get_mut(&name.lexeme) {
+                Some(slot) => {
+                    *slot = value;
+                    Ok(())
+                }
+                None => Err(undefined(name)),
+            };
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign_at(distance - 1, name, value),
+            None => Err(undefined(name)),
+        }
+    }
+}
+
+fn undefined(name: &Token) -> Error {
+    Error::runtime_error_at(name, &format!("Undefined variable '{}'.", name.lexeme))
+}
+
+fn constant(name: &Token) -> Error {
+    Error::runtime_error_at(name, &format!("Cannot assign to constant '{}'.", name.lexeme))
+}
@@ -1,39 +1,307 @@
-use crate::token::{Token, TokenType};
+use std::fmt::Display;
+
+use crate::token::{Object, Token, TokenType};
 
 pub type CblResult<T> = Result<T, Error>;
 
-pub fn error(line: u32, message: &str) {
-    report(line, "", message);
+/// Prints errors to stderr and remembers whether any have been reported,
+/// so the driver can decide on an exit code once it is done
+#[derive(Debug, Default)]
+pub struct ErrorReporter {
+    had_error: bool,
+    had_runtime_error: bool,
+    /// When set, errors are shown with the line of source they point at
+    source: Option<String>,
 }
 
-pub fn report(line: u32, where_: &str, message: &str) {
-    eprintln!("[line {}] Error {}: {}", line, where_, message);
-}
+impl ErrorReporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show the offending line of `source` under each reported error
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source = Some(source.to_string());
+        self
+    }
+
+    /// Report an error returned by the scanner, parser or interpreter,
+    /// `had_error` and `had_runtime_error` tell whether any have been
+    pub fn report_error(&mut self, error: &Error) {
+        match &self.source {
+            Some(source) => eprintln!("{}", error.render(source)),
+            None => eprintln!("{}", error),
+        }
+        match error {
+            Error::RuntimeError { .. } => self.had_runtime_error = true,
+            _ => self.had_error = true,
+        }
+    }
 
-pub fn parser_error(token: &Token, message: &str) {
-    if token.type_ == TokenType::Eof {
-        report(token.line, " at end", message);
-    } else {
-        report(token.line, &format!(" at '{}'", token.lexeme), message);
+    /// Print a warning, unlike errors these don't affect the exit code
+    pub fn report_warning(&mut self, warning: &Warning) {
+        eprintln!("{}", warning);
+    }
+
+    /// Whether a scanner or parser error has been reported
+    pub fn had_error(&self) -> bool {
+        self.had_error
+    }
+
+    pub fn had_runtime_error(&self) -> bool {
+        self.had_runtime_error
+    }
+
+    /// Forget about previous errors, eg. between lines of the REPL
+    pub fn reset(&mut self) {
+        self.had_error = false;
+        self.had_runtime_error = false;
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Error {
-    ParserError(String),
-    RuntimeError(String),
+    ScannerError {
+        message: String,
+        line: u32,
+        column: u32,
+        /// Byte offset into the source, like a token's `start`
+        offset: usize,
+    },
+    ParserError {
+        message: String,
+        line: u32,
+        column: u32,
+        /// Where on the line it went wrong, eg. " at ';'" or " at end"
+        where_: String,
+    },
+    RuntimeError {
+        message: String,
+        line: u32,
+        /// 0 when the error doesn't belong to a token, eg. from a native
+        column: u32,
+    },
+    /// Not really an error, a `return` statement unwinds out of the
+    /// function body with this until the call catches it
+    Return { value: Object },
+    /// Like `Return`, unwinds to the innermost loop
+    Break,
+    Continue,
 }
 
 impl Error {
-    pub fn new(message: &str) -> Error {
-        Error::ParserError(message.to_string())
+    pub fn scanner_error(line: u32, column: u32, offset: usize, message: &str) -> Error {
+        Error::ScannerError {
+            message: message.to_string(),
+            line,
+            column,
+            offset,
+        }
+    }
+
+    /// An error found while parsing `token`, the token that didn't fit
+    pub fn parser_error(token: &Token, message: &str) -> Error {
+        let where_ = if token.type_ == TokenType::Eof {
+            " at end".to_string()
+        } else {
+            format!(" at '{}'", token.lexeme)
+        };
+
+        Error::ParserError {
+            message: message.to_string(),
+            line: token.line,
+            column: token.column,
+            where_,
+        }
+    }
+
+    pub fn runtime_error(line: u32, message: &str) -> Error {
+        Error::RuntimeError {
+            message: message.to_string(),
+            line,
+            column: 0,
+        }
+    }
+
+    /// A runtime error caused by `token`, eg. the operator of a binary
+    /// expression with the wrong operands
+    pub fn runtime_error_at(token: &Token, message: &str) -> Error {
+        Error::RuntimeError {
+            message: message.to_string(),
+            line: token.line,
+            column: token.column,
+        }
+    }
+
+    /// Where the error happened as (line, column), None for errors that
+    /// don't point at a column
+    pub fn location(&self) -> Option<(u32, u32)> {
+        match self {
+            Error::ScannerError { line, column, .. }
+            | Error::ParserError { line, column, .. }
+            | Error::RuntimeError { line, column, .. } if *column > 0 => Some((*line, *column)),
+            _ => None,
+        }
     }
 
-    pub fn parser_error(message: &str) -> Error {
-        Error::ParserError(message.to_string())
+    /// The error followed by the line of `source` it happened on, with a
+    /// caret under the column, eg.
+    ///
+    /// ```text
+    /// [line 1] Parse error at ';': Expect expression.
+    ///   1 | var a = ;
+    ///     |         ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let (line, column) = match self.location() {
+            Some(location) => location,
+            None => return self.to_string(),
+        };
+        // count lines like the scanner does, where a "\r" on its own
+        // is a line break too
+        let source = source.replace("\r\n", "\n");
+        let text = match source.split(['\n', '\r']).nth(line as usize - 1) {
+            Some(text) => text,
+            None => return self.to_string(),
+        };
+
+        // keep tabs so the caret lines up with the text above it
+        let padding = text
+            .chars()
+            .take(column as usize - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let gutter = " ".repeat(line.to_string().len());
+        format!("{}\n {} | {}\n {} | {}^", self, line, text, gutter, padding)
     }
+}
 
-    pub fn runtime_error(message: &str) -> Error {
-        Error::RuntimeError(message.to_string())
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::ScannerError { message, line, .. } => {
+                write!(f, "[line {}] Scan error: {}", line, message)
+            }
+            Error::ParserError { message, line, where_, .. } => {
+                write!(f, "[line {}] Parse error{}: {}", line, where_, message)
+            }
+            Error::RuntimeError { message, line, .. } => {
+                write!(f, "[line {}] Runtime error: {}", line, message)
+            }
+            Error::Return { .. } => write!(f, "Can't return from top-level code."),
+            Error::Break => write!(f, "Can't use 'break' outside of a loop."),
+            Error::Continue => write!(f, "Can't use 'continue' outside of a loop."),
+        }
     }
-}
\ No newline at end of file
+}
+
+impl std::error::Error for Error {}
+
+/// Something suspicious about a program that doesn't stop it from
+/// running, eg. a statement that can never be reached
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub message: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Warning {
+    /// A warning about the code starting at `token`
+    pub fn at(token: &Token, message: &str) -> Warning {
+        Warning {
+            message: message.to_string(),
+            line: token.line,
+            column: token.column,
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] Warning: {}", self.line, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_parser_error() {
+        let token = Token::new(TokenType::Semicolon, ";".to_string(), Object::Nil, 3, 7);
+        let error = Error::parser_error(&token, "Expect expression.");
+        assert_eq!(error.to_string(), "[line 3] Parse error at ';': Expect expression.");
+
+        let eof = Token::new(TokenType::Eof, "".to_string(), Object::Nil, 4, 1);
+        let error = Error::parser_error(&eof, "Expect ';' after value.");
+        assert_eq!(error.to_string(), "[line 4] Parse error at end: Expect ';' after value.");
+    }
+
+    #[test]
+    fn test_display_runtime_error() {
+        let error = Error::runtime_error(3, "Operand must be a number.");
+        assert_eq!(error.to_string(), "[line 3] Runtime error: Operand must be a number.");
+    }
+
+    #[test]
+    fn test_boxed_error() {
+        let error: Box<dyn std::error::Error> = Box::new(Error::scanner_error(1, 2, 1, "Unexpected character '@'."));
+        assert_eq!(error.to_string(), "[line 1] Scan error: Unexpected character '@'.");
+    }
+
+    #[test]
+    fn test_reporter_flags() {
+        let mut reporter = ErrorReporter::new();
+        assert!(!reporter.had_error());
+
+        reporter.report_error(&Error::scanner_error(1, 1, 0, "Unexpected character '@'."));
+        assert!(reporter.had_error());
+        assert!(!reporter.had_runtime_error());
+
+        reporter.reset();
+        assert!(!reporter.had_error());
+
+        reporter.report_error(&Error::runtime_error(2, "Operand must be a number."));
+        assert!(!reporter.had_error());
+        assert!(reporter.had_runtime_error());
+    }
+
+    #[test]
+    fn test_render_error() {
+        let source = "var a = 1;\nvar b = ;\n";
+        let token = Token::new(TokenType::Semicolon, ";".to_string(), Object::Nil, 2, 9);
+        let error = Error::parser_error(&token, "Expect expression.");
+        assert_eq!(
+            error.render(source),
+            "[line 2] Parse error at ';': Expect expression.\n 2 | var b = ;\n   |         ^"
+        );
+
+        // a "\r" on its own ends a line, as it does in the scanner
+        for source in ["var a = 1;\rvar b = ;\r", "var a = 1;\r\nvar b = ;\r\n"] {
+            assert_eq!(
+                error.render(source),
+                "[line 2] Parse error at ';': Expect expression.\n 2 | var b = ;\n   |         ^"
+            );
+        }
+
+        // tabs are kept so the caret still lines up
+        let token = Token::new(TokenType::Minus, "-".to_string(), Object::Nil, 1, 3);
+        let error = Error::runtime_error_at(&token, "Operand must be a number.");
+        assert_eq!(
+            error.render("\t\t-\"a\";"),
+            "[line 1] Runtime error: Operand must be a number.\n 1 | \t\t-\"a\";\n   | \t\t^"
+        );
+
+        // without a column there is nothing to point at
+        let error = Error::runtime_error(1, "Stack overflow.");
+        assert_eq!(error.render("f();"), "[line 1] Runtime error: Stack overflow.");
+    }
+
+    #[test]
+    fn test_display_warning() {
+        let token = Token::new(TokenType::Print, "print".to_string(), Object::Nil, 5, 3);
+        let warning = Warning::at(&token, "Unreachable statement after 'return'.");
+        assert_eq!(warning.to_string(), "[line 5] Warning: Unreachable statement after 'return'.");
+    }
+}
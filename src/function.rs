@@ -0,0 +1,82 @@
+use std::{cell::RefCell, fmt::Debug, rc::Rc};
+
+use crate::{
+    ast::Stmt,
+    callable::Callable,
+    environment::Environment,
+    error::{CblResult, Error},
+    interpreter::Interpreter,
+    token::{Object, Token, TokenType},
+};
+
+/// A function declared in a script with "fun name(params) { body }"
+#[derive(Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Token>,
+    /// Shared so that copying the function around doesn't copy its body
+    pub body: Rc<Vec<Stmt>>,
+    /// The scope the function was declared in, so it can still see the
+    /// variables around it after that scope has finished running
+    pub closure: Rc<RefCell<Environment>>,
+    /// A class's `init` method, which always gives back `this`
+    pub is_initializer: bool,
+}
+
+impl Function {
+    pub fn new(name: &Token, params: &[Token], body: &[Stmt], closure: Rc<RefCell<Environment>>) -> Self {
+        Function {
+            name: name.lexeme.clone(),
+            params: params.to_vec(),
+            body: Rc::new(body.to_vec()),
+            closure,
+            is_initializer: false,
+        }
+    }
+
+    /// A copy of this method whose `this` is `instance`
+    pub fn bind(&self, instance: Object) -> Function {
+        let mut environment = Environment::with_enclosing(self.closure.clone());
+        environment.define("this", instance);
+        Function {
+            closure: Rc::new(RefCell::new(environment)),
+            ..self.clone()
+        }
+    }
+
+    fn this(&self) -> CblResult<Object> {
+        self.closure.borrow().get_at(0, &Token::new(TokenType::This, "this".to_string(), Object::Nil, 0, 0))
+    }
+}
+
+impl Callable for Function {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Run the body in a new scope with the parameters bound to
+    /// `arguments`
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Object>) -> CblResult<Object> {
+        let mut environment = Environment::with_enclosing(self.closure.clone());
+        for (param, argument) in self.params.iter().zip(arguments) {
+            environment.define(&param.lexeme, argument);
+        }
+
+        match interpreter.execute_block(&self.body, environment) {
+            Ok(()) | Err(Error::Return { .. }) if self.is_initializer => self.this(),
+            Ok(()) => Ok(Object::Nil),
+            Err(Error::Return { value }) => Ok(value),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+impl Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.name)
+    }
+}
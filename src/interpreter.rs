@@ -5,142 +5,1951 @@ use crate::token::{
 };
 use crate::ast::{
     Visitor,
+    StmtVisitor,
     Expr,
+    Stmt,
 };
+use crate::environment::{EnvSnapshot, Environment};
+use crate::callable::Callable;
+use crate::class::{Class, Instance};
+use crate::function::Function;
+use crate::native::{define_natives, string_property};
+use crate::resolver::Resolver;
 
-pub struct Interpreter {}
+use std::{
+    cell::{Ref, RefCell},
+    cmp::Ordering,
+    collections::HashMap,
+    io::{self, Write},
+    rc::Rc,
+};
+
+pub struct Interpreter {
+    /// The outermost scope, where natives and top-level declarations live
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    /// How many scopes out each local variable reference lives, keyed by
+    /// expression id. Anything missing is a global
+    locals: HashMap<usize, usize>,
+    /// Where `print` writes to, stdout unless told otherwise
+    out: Box<dyn Write>,
+    /// When set, `print` appends here instead of writing to `out`
+    output: Option<Vec<String>>,
+    /// When set, numbers within this distance of each other are `==`
+    float_tolerance: Option<f64>,
+    /// How many calls are currently running
+    call_depth: usize,
+    max_call_depth: usize,
+    /// How many statements have run, counted against `step_limit`
+    steps: usize,
+    /// When set, fail once this many statements have run
+    step_limit: Option<usize>,
+    /// Called with the line of every statement before it runs, eg. for
+    /// breakpoints or tracing
+    on_statement: Option<Box<dyn FnMut(u32)>>,
+}
+
+/// How deeply script functions may call each other unless told
+/// otherwise, see `Interpreter::with_max_call_depth`. Deep recursion
+/// needs a bigger stack than a thread gets by default, see
+/// `runner::with_large_stack`
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 500;
 
 impl Visitor<Object> for Interpreter {
+    fn visit_array_expr(&mut self, elements: &[Expr]) -> CblResult<Object> {
+        let elements = elements
+            .iter()
+            .map(|element| self.evaluate(element))
+            .collect::<CblResult<Vec<Object>>>()?;
+        Ok(Object::Array(Rc::new(RefCell::new(elements))))
+    }
+
+    fn visit_assign_expr(&mut self, id: usize, name: &Token, value: &Expr) -> CblResult<Object> {
+        let value = self.evaluate(value)?;
+        self.assign_variable(id, name, value.clone())?;
+        Ok(value)
+    }
 
-    fn visit_binary_expr(&self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<Object> {
+    fn visit_binary_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<Object> {
         let l = self.evaluate(left)?;
         let r = self.evaluate(right)?;
-        
-        // this is so much better than it looks in java because of match 
-        match operator.type_ {
-            // Numeric Operations
-            TokenType::Minus => match (l, r) {
-                (Object::Number(l), Object::Number(r)) => Ok(Object::Number(l - r)),
-                _ => Err(Error::runtime_error(&format!("Expected numbers for Minus operation: {:?}", operator.type_))),
-            },
-            TokenType::Slash => match (l, r) {
-                (Object::Number(l), Object::Number(r)) => Ok(Object::Number(l / r)),
-                _ => Err(Error::runtime_error(&format!("Expected numbers for Slash operation: {:?}", operator.type_))),
-            },
-            TokenType::Star => match (l, r) {
-                (Object::Number(l), Object::Number(r)) => Ok(Object::Number(l * r)),
-                _ => Err(Error::runtime_error(&format!("Expected numbers for Star operation: {:?}", operator.type_))),
-            },
-            TokenType::Plus => match (l, r) {
-                (Object::Number(l), Object::Number(r)) => Ok(Object::Number(l + r)),
-                (Object::String(l), Object::String(r)) => Ok(Object::String(l + &r)),
-                _ => Err(Error::runtime_error(&format!("Expected numbers or strings for Plus operation: {:?}", operator.type_))),
-            },
-            
-            // Boolean Operations
-            TokenType::Greater => match (l, r) {
-                (Object::Number(l), Object::Number(r)) => Ok(Object::Bool(l > r)),
-                _ => Err(Error::runtime_error(&format!("Expected numbers for Greater operation: {:?}", operator.type_))),
-            },
-            TokenType::GreaterEqual => match (l, r) {
-                (Object::Number(l), Object::Number(r)) => Ok(Object::Bool(l >= r)),
-                _ => Err(Error::runtime_error(&format!("Expected numbers for GreaterEqual operation: {:?}", operator.type_))),
-            },
-            TokenType::Less => match (l, r) {
-                (Object::Number(l), Object::Number(r)) => Ok(Object::Bool(l < r)),
-                _ => Err(Error::runtime_error(&format!("Expected numbers for Less operation: {:?}", operator.type_))),
-            },
-            TokenType::LessEqual => match (l, r) {
-                (Object::Number(l), Object::Number(r)) => Ok(Object::Bool(l <= r)),
-                _ => Err(Error::runtime_error(&format!("Expected numbers for Less operation: {:?}", operator.type_))),
-            },
-            TokenType::BangEqual => Ok(Object::Bool(!self.is_equal(&l, &r))),
-            TokenType::EqualEqual => Ok(Object::Bool(self.is_equal(&l, &r))),
-            _ => Err(Error::runtime_error(&format!("Unexpected token type: {:?}", operator.type_))),
-        }
+
+        self.binary(l, operator, r)
+    }
+
+    fn visit_call_expr(&mut self, callee: &Expr, paren: &Token, arguments: &[Expr]) -> CblResult<Object> {
+        let callee = self.evaluate(callee)?;
+
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.evaluate(argument))
+            .collect::<CblResult<Vec<Object>>>()?;
+
+        let callable: Rc<dyn Callable> = match callee {
+            Object::Callable(callable) => callable,
+            Object::Class(class) => class,
+            _ => return Err(Error::runtime_error_at(paren, "Can only call functions and classes.")),
+        };
+
+        self.call(paren, callable.as_ref(), arguments)
+    }
+
+    fn visit_compound_assign_expr(&mut self, target: &Expr, operator: &Token, value: &Expr) -> CblResult<Object> {
+        let (_, new) = self.update(target, operator, "Invalid assignment target.", |interpreter, old| {
+            let value = interpreter.evaluate(value)?;
+            interpreter.binary(old.clone(), operator, value)
+        })?;
+        Ok(new)
+    }
+
+    fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> CblResult<Object> {
+        let object = self.evaluate(object)?;
+        get_property(name, &object)
     }
 
-    fn visit_grouping_expr(&self, expression: &Expr) -> CblResult<Object> {
+    fn visit_grouping_expr(&mut self, expression: &Expr) -> CblResult<Object> {
         self.evaluate(expression)
     }
 
-    fn visit_literal_expr(&self, value: &Object) -> CblResult<Object> {
+    fn visit_index_expr(&mut self, object: &Expr, bracket: &Token, index: &Expr) -> CblResult<Object> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        get_index(bracket, &object, &index)
+    }
+
+    fn visit_index_assign_expr(&mut self, object: &Expr, bracket: &Token, index: &Expr, value: &Expr) -> CblResult<Object> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        let value = self.evaluate(value)?;
+        set_index(bracket, &object, &index, value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_literal_expr(&mut self, value: &Object) -> CblResult<Object> {
         Ok(value.clone())
     }
 
-    fn visit_unary_expr(&self, operator: &Token, right: &Expr) -> CblResult<Object> {
+    fn visit_logical_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> CblResult<Object> {
+        let l = self.evaluate(left)?;
+
+        // return the operand itself rather than a bool, so "nil or 2" is 2.
+        // "??" only moves on to the right for nil, so "false ?? 2" is false
+        match operator.type_ {
+            TokenType::QuestionQuestion if !matches!(l, Object::Nil) => Ok(l),
+            TokenType::Or if self.is_truthy(&l) => Ok(l),
+            TokenType::And if !self.is_truthy(&l) => Ok(l),
+            _ => self.evaluate(right),
+        }
+    }
+
+    fn visit_map_expr(&mut self, brace: &Token, entries: &[(Expr, Expr)]) -> CblResult<Object> {
+        let mut map = HashMap::new();
+        for (key, value) in entries {
+            let key = self.evaluate(key)?;
+            let key = map_key(brace, &key)?.to_string();
+            map.insert(key, self.evaluate(value)?);
+        }
+        Ok(Object::Map(Rc::new(RefCell::new(map))))
+    }
+
+    fn visit_postfix_expr(&mut self, target: &Expr, operator: &Token) -> CblResult<Object> {
+        let step = |old: &Object| {
+            let delta = match operator.type_ {
+                TokenType::PlusPlus => 1,
+                _ => -1,
+            };
+            arithmetic(old, &Object::Int(delta), i64::checked_add, |l, r| l + r).ok_or_else(|| {
+                Error::runtime_error_at(operator, &format!("Operand of '{}' must be a number.", operator.lexeme))
+            })
+        };
+
+        let (old, _) = self.update(target, operator, "Invalid increment target.", |_, old| step(old))?;
+        Ok(old)
+    }
+
+    fn visit_set_expr(&mut self, object: &Expr, name: &Token, value: &Expr) -> CblResult<Object> {
+        let object = self.evaluate(object)?;
+        let value = self.evaluate(value)?;
+        set_property(name, &object, value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_super_expr(&mut self, id: usize, keyword: &Token, method: &str) -> CblResult<Object> {
+        // the resolver always finds `super`, with `this` one scope closer
+        let distance = self.locals.get(&id).copied().unwrap_or_default();
+        let superclass = self.environment.borrow().get_at(distance, keyword)?;
+        let mut this = keyword.clone();
+        this.lexeme = "this".to_string();
+        let object = self.environment.borrow().get_at(distance - 1, &this)?;
+
+        match superclass {
+            Object::Class(class) => match class.find_method(method) {
+                Some(function) => Ok(Object::Callable(Rc::new(function.bind(object)))),
+                None => Err(Error::runtime_error_at(keyword, &format!("Undefined property '{}'.", method))),
+            },
+            _ => Err(Error::runtime_error_at(keyword, "Superclass must be a class.")),
+        }
+    }
+
+    fn visit_this_expr(&mut self, id: usize, keyword: &Token) -> CblResult<Object> {
+        self.visit_variable_expr(id, keyword)
+    }
+
+    fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> CblResult<Object> {
         let r = self.evaluate(right)?;
     
         match operator.type_ {
-            TokenType::Bang => match r {
-                Object::Bool(r) => Ok(Object::Bool(!r)),
-                _ => Err(Error::runtime_error(&format!("Operand must be a bool: {:?}", operator.type_)))
-            },
+            // works on any value, so "!nil" is true and "!0" is false
+            TokenType::Bang => Ok(Object::Bool(!self.is_truthy(&r))),
             TokenType::Minus => match r {
+                Object::Int(r) => Ok(r.checked_neg().map_or(Object::Number(-(r as f64)), Object::Int)),
                 Object::Number(r) => Ok(Object::Number(-r)),
-                _ => Err(Error::runtime_error(&format!("Operand must be a number: {:?}", operator.type_))),
+                _ => Err(Error::runtime_error_at(operator, &format!("Operand must be a number: {:?}", operator.type_))),
+            },
+            // unary plus converts strings to numbers, eg. +"42" is 42
+            TokenType::Plus => match r {
+                Object::Int(r) => Ok(Object::Int(r)),
+                Object::Number(r) => Ok(Object::Number(r)),
+                Object::String(s) => match (s.trim().parse::<i64>(), s.trim().parse::<f64>()) {
+                    (Ok(n), _) => Ok(Object::Int(n)),
+                    (_, Ok(n)) => Ok(Object::Number(n)),
+                    _ => Err(Error::runtime_error_at(operator, &format!("Cannot convert \"{}\" to a number.", s))),
+                },
+                _ => Err(Error::runtime_error_at(operator, &format!("Operand must be a number or string: {:?}", operator.type_))),
             },
-            _ => Err(Error::runtime_error(&format!("Unexpected token type: {:?}", operator.type_))),
+            _ => Err(Error::runtime_error_at(operator, &format!("Unexpected token type: {:?}", operator.type_))),
+        }
+    }
+
+    fn visit_variable_expr(&mut self, id: usize, name: &Token) -> CblResult<Object> {
+        match self.locals.get(&id) {
+            Some(distance) => self.environment.borrow().get_at(*distance, name),
+            None => self.globals.borrow().get(name),
         }
     }
     
 }
 
+impl StmtVisitor<()> for Interpreter {
+    fn visit_assert_stmt(&mut self, keyword: &Token, condition: &Expr, message: &Option<Expr>) -> CblResult<()> {
+        let condition = self.evaluate(condition)?;
+        if self.is_truthy(&condition) {
+            return Ok(());
+        }
+
+        // the message is only evaluated when the assertion fails
+        match message {
+            Some(message) => {
+                let message = self.evaluate(message)?;
+                Err(Error::runtime_error_at(keyword, &format!("Assertion failed: {}", message)))
+            }
+            None => Err(Error::runtime_error_at(keyword, "Assertion failed.")),
+        }
+    }
+
+    fn visit_block_stmt(&mut self, statements: &[Stmt]) -> CblResult<()> {
+        let environment = Environment::with_enclosing(self.environment.clone());
+        self.execute_block(statements, environment)
+    }
+
+    fn visit_break_stmt(&mut self, _keyword: &Token) -> CblResult<()> {
+        Err(Error::Break)
+    }
+
+    fn visit_const_stmt(&mut self, name: &Token, initializer: &Expr) -> CblResult<()> {
+        let value = self.evaluate(initializer)?;
+        self.environment.borrow_mut().define_constant(&name.lexeme, value);
+        Ok(())
+    }
+
+    fn visit_continue_stmt(&mut self, _keyword: &Token) -> CblResult<()> {
+        Err(Error::Continue)
+    }
+
+    fn visit_expression_stmt(&mut self, expression: &Expr) -> CblResult<()> {
+        self.evaluate(expression)?;
+        Ok(())
+    }
+
+    fn visit_class_stmt(&mut self, name: &Token, superclass: &Option<Expr>, methods: &[Stmt]) -> CblResult<()> {
+        let superclass = match superclass {
+            Some(expr) => match self.evaluate(expr)? {
+                Object::Class(class) => Some(class),
+                _ => {
+                    let token = match expr {
+                        Expr::Variable { name, .. } => name,
+                        _ => name,
+                    };
+                    return Err(Error::runtime_error_at(token, "Superclass must be a class."));
+                }
+            },
+            None => None,
+        };
+
+        // methods of a subclass close over a scope holding `super`
+        let closure = match &superclass {
+            Some(superclass) => {
+                let mut environment = Environment::with_enclosing(self.environment.clone());
+                environment.define("super", Object::Class(superclass.clone()));
+                Rc::new(RefCell::new(environment))
+            }
+            None => self.environment.clone(),
+        };
+
+        let mut functions = HashMap::new();
+        for method in methods {
+            if let Stmt::Function { name, params, body } = method {
+                let mut function = Function::new(name, params, body, closure.clone());
+                function.is_initializer = name.lexeme == "init";
+                functions.insert(name.lexeme.clone(), function);
+            }
+        }
+
+        let class = Class::new(&name.lexeme, superclass, functions);
+        self.environment.borrow_mut().define(&name.lexeme, Object::Class(Rc::new(class)));
+        Ok(())
+    }
+
+    fn visit_for_each_stmt(&mut self, name: &Token, iterable: &Expr, body: &Stmt) -> CblResult<()> {
+        // arrays are copied first, so pushing to one inside the loop
+        // doesn't make it run forever
+        let items = match self.evaluate(iterable)? {
+            Object::Array(elements) => elements.borrow().clone(),
+            Object::String(s) => s.chars().map(|c| Object::String(c.to_string())).collect(),
+            other => {
+                let message = format!("Can only loop over arrays and strings, got {}.", other.kind());
+                return Err(Error::runtime_error_at(name, &message));
+            }
+        };
+
+        for item in items {
+            let mut environment = Environment::with_enclosing(self.environment.clone());
+            environment.define(&name.lexeme, item);
+            match self.execute_block(std::slice::from_ref(body), environment) {
+                Ok(()) | Err(Error::Continue) => {}
+                Err(Error::Break) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_function_stmt(&mut self, name: &Token, params: &[Token], body: &[Stmt]) -> CblResult<()> {
+        let function = Function::new(name, params, body, self.environment.clone());
+        self.environment.borrow_mut().define(&name.lexeme, Object::Callable(Rc::new(function)));
+        Ok(())
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> CblResult<()> {
+        let condition = self.evaluate(condition)?;
+
+        if self.is_truthy(&condition) {
+            self.execute(then_branch)
+        } else if let Some(else_branch) = else_branch {
+            self.execute(else_branch)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn visit_print_stmt(&mut self, expression: &Expr) -> CblResult<()> {
+        let value = self.evaluate(expression)?;
+        self.print(&value)
+    }
+
+    fn visit_return_stmt(&mut self, _keyword: &Token, value: &Option<Expr>) -> CblResult<()> {
+        let value = match value {
+            Some(value) => self.evaluate(value)?,
+            None => Object::Nil,
+        };
+
+        Err(Error::Return { value })
+    }
+
+    fn visit_var_stmt(&mut self, name: &Token, initializer: &Option<Expr>) -> CblResult<()> {
+        let value = match initializer {
+            Some(expr) => self.evaluate(expr)?,
+            None => Object::Nil,
+        };
+
+        self.environment.borrow_mut().define(&name.lexeme, value);
+        Ok(())
+    }
+
+    fn visit_while_stmt(&mut self, condition: &Expr, body: &Stmt, increment: &Option<Expr>) -> CblResult<()> {
+        loop {
+            let value = self.evaluate(condition)?;
+            if !self.is_truthy(&value) {
+                return Ok(());
+            }
+
+            match self.execute(body) {
+                Ok(()) | Err(Error::Continue) => {}
+                Err(Error::Break) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Interpreter {
     pub fn new() -> Self {
-        Interpreter {}
+        let mut globals = Environment::new();
+        define_natives(&mut globals);
+        let globals = Rc::new(RefCell::new(globals));
+
+        Interpreter {
+            globals: globals.clone(),
+            environment: globals,
+            locals: HashMap::new(),
+            out: Box::new(io::stdout()),
+            output: None,
+            float_tolerance: None,
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            steps: 0,
+            step_limit: None,
+            on_statement: None,
+        }
+    }
+
+    /// Write everything printed to `out` rather than stdout, eg. a file
+    /// or a buffer owned by whatever is embedding the interpreter
+    pub fn with_writer(mut self, out: impl Write + 'static) -> Self {
+        self.out = Box::new(out);
+        self
+    }
+
+    /// Collect everything printed, one entry per `print`, rather than
+    /// writing it out. Read it back with `output`
+    pub fn with_captured_output(mut self) -> Self {
+        self.output = Some(Vec::new());
+        self
+    }
+
+    /// Treat numbers as equal when they are within `epsilon` of each
+    /// other, so that "0.1 + 0.2 == 0.3" is true. By default numbers
+    /// are only equal when they are exactly the same
+    pub fn with_float_tolerance(mut self, epsilon: f64) -> Self {
+        self.float_tolerance = Some(epsilon);
+        self
+    }
+
+    /// Fail with a runtime error once more than `max_call_depth` calls
+    /// are running at once, rather than overflowing the native stack
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    /// Fail with a runtime error once `step_limit` statements have run,
+    /// so that "while (true) {}" can't hang whatever is embedding the
+    /// interpreter. By default there is no limit
+    pub fn with_step_limit(mut self, step_limit: usize) -> Self {
+        self.step_limit = Some(step_limit);
+        self
+    }
+
+    /// Call `on_statement` with each statement's line before running it,
+    /// which is enough to build breakpoints or a trace on top of
+    pub fn with_on_statement(mut self, on_statement: impl FnMut(u32) + 'static) -> Self {
+        self.on_statement = Some(Box::new(on_statement));
+        self
+    }
+
+    /// Everything printed so far, empty unless output is being captured
+    pub fn output(&self) -> &[String] {
+        self.output.as_deref().unwrap_or(&[])
     }
 
-    fn evaluate(&self, expr: &Expr) -> CblResult<Object> {
+    fn assign_variable(&mut self, id: usize, name: &Token, value: Object) -> CblResult<()> {
+        match self.locals.get(&id) {
+            Some(distance) => self.environment.borrow_mut().assign_at(*distance, name, value),
+            None => self.globals.borrow_mut().assign(name, value),
+        }
+    }
+
+    /// Write `value` on its own line, shared by the `print` statement and
+    /// the `print()` native
+    pub(crate) fn print(&mut self, value: &Object) -> CblResult<()> {
+        match &mut self.output {
+            Some(output) => output.push(value.to_string()),
+            None => writeln!(self.out, "{}", value)
+                .map_err(|e| Error::runtime_error(0, &format!("Could not write output: {}.", e)))?,
+        }
+        Ok(())
+    }
+
+    /// Copy the global variables so they can be put back with `restore`,
+    /// eg. to undo a statement in a notebook. This clones every global
+    /// binding, so it costs time and memory in proportion to how many
+    /// there are. Arrays and maps are shared rather than copied, so
+    /// changes made to their elements in the meantime are kept
+    pub fn snapshot(&self) -> EnvSnapshot {
+        self.globals.borrow().snapshot()
+    }
+
+    /// The global scope, eg. to look at what a program left behind
+    pub fn globals(&self) -> Ref<'_, Environment> {
+        self.globals.borrow()
+    }
+
+    /// Forget every global a program has defined, leaving only the
+    /// natives, as if the interpreter had just been made. Options set
+    /// with the `with_*` methods and captured output are kept
+    pub fn reset(&mut self) {
+        let mut globals = Environment::new();
+        define_natives(&mut globals);
+        self.globals = Rc::new(RefCell::new(globals));
+        self.environment = self.globals.clone();
+        self.locals.clear();
+        self.call_depth = 0;
+        self.steps = 0;
+    }
+
+    /// Go back to the globals from an earlier `snapshot`. The scope is
+    /// restored in place, so functions declared before the snapshot
+    /// still see the restored values
+    pub fn restore(&mut self, snapshot: EnvSnapshot) {
+        self.globals.borrow_mut().restore(snapshot);
+    }
+
+    /// Called by the resolver, the variable expression `id` refers to a
+    /// local declared `depth` scopes out from where it is used
+    pub fn resolve(&mut self, id: usize, depth: usize) {
+        self.locals.insert(id, depth);
+    }
+
+    /// Evaluate a single expression in the current environment
+    pub fn evaluate(&mut self, expr: &Expr) -> CblResult<Object> {
         expr.accept(self)
     }
 
+    fn execute(&mut self, stmt: &Stmt) -> CblResult<()> {
+        if let Some(step_limit) = self.step_limit {
+            if self.steps >= step_limit {
+                return Err(Error::runtime_error(stmt.line(), "Step limit exceeded."));
+            }
+            self.steps += 1;
+        }
+        if let Some(on_statement) = &mut self.on_statement {
+            on_statement(stmt.line());
+        }
+        stmt.accept(self)
+    }
+
+    /// Run `statements` inside of `environment`, restoring the current
+    /// environment afterwards even if one of the statements fails
+    pub(crate) fn execute_block(&mut self, statements: &[Stmt], environment: Environment) -> CblResult<()> {
+        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+
+        let result = statements.iter().try_for_each(|statement| self.execute(statement));
+
+        self.environment = previous;
+        result
+    }
+
+    /// `nil` and `false` are falsey, every other value is truthy,
+    /// including `0` and `""`. Every condition (`if`, `while`, `and`,
+    /// `or`) goes through here so they all agree
+    pub fn is_truthy(&self, object: &Object) -> bool {
+        !matches!(object, Object::Nil | Object::Bool(false))
+    }
+
+    /// `==` follows IEEE 754 for numbers: `0.0 == -0.0` is true and NaN
+    /// isn't equal to anything, not even itself. `nil == nil` is true and
+    /// values of different kinds are never equal
     fn is_equal(&self, a: &Object, b: &Object) -> bool {
         match (a, b) {
             (Object::Nil, Object::Nil) => true,
-            (Object::Number(a), Object::Number(b)) => a == b,
+            (Object::Int(a), Object::Int(b)) => a == b,
+            // an int and a float are equal if they are the same number
+            (Object::Int(_) | Object::Number(_), Object::Int(_) | Object::Number(_)) => {
+                let (a, b) = (a.as_number().unwrap_or_default(), b.as_number().unwrap_or_default());
+                match self.float_tolerance {
+                    // inf - inf is NaN, so exact matches are checked first
+                    Some(epsilon) => a == b || (a - b).abs() <= epsilon,
+                    None => a == b,
+                }
+            }
             (Object::String(a), Object::String(b)) => a == b,
             (Object::Bool(a), Object::Bool(b)) => a == b,
+            (Object::Callable(a), Object::Callable(b)) => Rc::ptr_eq(a, b),
+            (Object::Array(a), Object::Array(b)) => Rc::ptr_eq(a, b),
+            (Object::Map(a), Object::Map(b)) => Rc::ptr_eq(a, b),
+            (Object::Class(a), Object::Class(b)) => Rc::ptr_eq(a, b),
+            (Object::Instance(a), Object::Instance(b)) => Rc::ptr_eq(a, b),
             _ => false,
         }
     }
 
-    pub fn interpret(&self, expr: &Expr) -> CblResult<Object> {
-        self.evaluate(expr)
+    /// Apply the binary `operator` to two values that have already been
+    /// evaluated, shared by binary expressions and compound assignment
+    fn binary(&mut self, l: Object, operator: &Token, r: Object) -> CblResult<Object> {
+        if let Object::Instance(instance) = &l {
+            if let Some(result) = self.call_operator_method(instance, operator, &r) {
+                return result;
+            }
+        }
+        
+        // this is so much better than it looks in java because of match 
+        match operator.type_ {
+            // Numeric Operations, ints stay ints unless mixed with a float
+            TokenType::Minus => arithmetic(&l, &r, i64::checked_sub, |l, r| l - r)
+                .ok_or_else(|| Error::runtime_error_at(operator, &format!("Expected numbers for Minus operation: {:?}", operator.type_))),
+            // division always gives a float, so 5 / 2 is 2.5. Dividing
+            // by zero is an error rather than infinity or NaN
+            TokenType::Slash => match (l.as_number(), r.as_number()) {
+                (Some(_), Some(0.0)) => Err(Error::runtime_error_at(operator, "Division by zero.")),
+                (Some(l), Some(r)) => Ok(Object::Number(l / r)),
+                _ => Err(Error::runtime_error_at(operator, &format!("Expected numbers for Slash operation: {:?}", operator.type_))),
+            },
+            TokenType::Star => arithmetic(&l, &r, i64::checked_mul, |l, r| l * r)
+                .ok_or_else(|| Error::runtime_error_at(operator, &format!("Expected numbers for Star operation: {:?}", operator.type_))),
+            TokenType::StarStar => arithmetic(&l, &r, |l, r| l.checked_pow(u32::try_from(r).ok()?), f64::powf)
+                .ok_or_else(|| Error::runtime_error_at(operator, &format!("Expected numbers for StarStar operation: {:?}", operator.type_))),
+            TokenType::Percent => match (l.as_number(), r.as_number()) {
+                (Some(_), Some(0.0)) => Err(Error::runtime_error_at(operator, "Modulo by zero.")),
+                _ => arithmetic(&l, &r, i64::checked_rem, |l, r| l % r)
+                    .ok_or_else(|| Error::runtime_error_at(operator, &format!("Expected numbers for Percent operation: {:?}", operator.type_))),
+            },
+            // Bitwise Operations, only on numbers with no fractional part
+            TokenType::Ampersand => bitwise(&l, &r, |l, r| Some(l & r))
+                .ok_or_else(|| Error::runtime_error_at(operator, &format!("Expected integers for Ampersand operation: {:?}", operator.type_))),
+            TokenType::Pipe => bitwise(&l, &r, |l, r| Some(l | r))
+                .ok_or_else(|| Error::runtime_error_at(operator, &format!("Expected integers for Pipe operation: {:?}", operator.type_))),
+            TokenType::Caret => bitwise(&l, &r, |l, r| Some(l ^ r))
+                .ok_or_else(|| Error::runtime_error_at(operator, &format!("Expected integers for Caret operation: {:?}", operator.type_))),
+            TokenType::LessLess => bitwise(&l, &r, |l, r| l.checked_shl(u32::try_from(r).ok()?))
+                .ok_or_else(|| Error::runtime_error_at(operator, &format!("Expected integers and a shift below 64 for LessLess operation: {:?}", operator.type_))),
+            TokenType::GreaterGreater => bitwise(&l, &r, |l, r| l.checked_shr(u32::try_from(r).ok()?))
+                .ok_or_else(|| Error::runtime_error_at(operator, &format!("Expected integers and a shift below 64 for GreaterGreater operation: {:?}", operator.type_))),
+            TokenType::Plus => match (l, r) {
+                // when either side is a string the other side is converted
+                // with its Display impl, so "n=" + 5 is "n=5"
+                (Object::String(l), r) => Ok(Object::String(format!("{}{}", l, r))),
+                (l, Object::String(r)) => Ok(Object::String(format!("{}{}", l, r))),
+                (l, r) => arithmetic(&l, &r, i64::checked_add, |l, r| l + r)
+                    .ok_or_else(|| Error::runtime_error_at(operator, &format!("Expected numbers or strings for Plus operation: {:?}", operator.type_))),
+            },
+            
+            // Boolean Operations, strings are compared lexicographically
+            TokenType::Greater => match compare(&l, &r) {
+                Some(ordering) => Ok(Object::Bool(ordering == Some(Ordering::Greater))),
+                None => Err(Error::runtime_error_at(operator, &format!("Expected two numbers or two strings for Greater operation: {:?}", operator.type_))),
+            },
+            TokenType::GreaterEqual => match compare(&l, &r) {
+                Some(ordering) => Ok(Object::Bool(matches!(ordering, Some(Ordering::Greater | Ordering::Equal)))),
+                None => Err(Error::runtime_error_at(operator, &format!("Expected two numbers or two strings for GreaterEqual operation: {:?}", operator.type_))),
+            },
+            TokenType::Less => match compare(&l, &r) {
+                Some(ordering) => Ok(Object::Bool(ordering == Some(Ordering::Less))),
+                None => Err(Error::runtime_error_at(operator, &format!("Expected two numbers or two strings for Less operation: {:?}", operator.type_))),
+            },
+            TokenType::LessEqual => match compare(&l, &r) {
+                Some(ordering) => Ok(Object::Bool(matches!(ordering, Some(Ordering::Less | Ordering::Equal)))),
+                None => Err(Error::runtime_error_at(operator, &format!("Expected two numbers or two strings for LessEqual operation: {:?}", operator.type_))),
+            },
+            TokenType::BangEqual => Ok(Object::Bool(!self.is_equal(&l, &r))),
+            TokenType::EqualEqual => Ok(Object::Bool(self.is_equal(&l, &r))),
+            _ => Err(Error::runtime_error_at(operator, &format!("Unexpected token type: {:?}", operator.type_))),
+        }
+    }
+
+    /// Replace the variable, element or field `target` with what `step`
+    /// makes of its old value, giving back the old and new values. The
+    /// object and index are only evaluated once, so "a[f()]++" calls f once
+    fn update(
+        &mut self,
+        target: &Expr,
+        operator: &Token,
+        invalid: &str,
+        step: impl FnOnce(&mut Self, &Object) -> CblResult<Object>,
+    ) -> CblResult<(Object, Object)> {
+        match target {
+            Expr::Variable { id, name } => {
+                let old = self.visit_variable_expr(*id, name)?;
+                let new = step(self, &old)?;
+                self.assign_variable(*id, name, new.clone())?;
+                Ok((old, new))
+            }
+            Expr::Index { object, bracket, index } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let old = get_index(bracket, &object, &index)?;
+                let new = step(self, &old)?;
+                set_index(bracket, &object, &index, new.clone())?;
+                Ok((old, new))
+            }
+            Expr::Get { object, name } => {
+                let object = self.evaluate(object)?;
+                let old = get_property(name, &object)?;
+                let new = step(self, &old)?;
+                set_property(name, &object, new.clone())?;
+                Ok((old, new))
+            }
+            _ => Err(Error::runtime_error_at(operator, invalid)),
+        }
+    }
+
+    /// Call `callable`, reporting errors from it at `paren`
+    fn call(&mut self, paren: &Token, callable: &dyn Callable, arguments: Vec<Object>) -> CblResult<Object> {
+        check_arity(paren, callable, arguments.len())?;
+        if self.call_depth >= self.max_call_depth {
+            return Err(Error::runtime_error_at(paren, "Stack overflow."));
+        }
+
+        self.call_depth += 1;
+        let result = callable.call(self, arguments);
+        self.call_depth -= 1;
+
+        // natives report errors on line 0, point them at the call instead
+        match result {
+            Err(Error::RuntimeError { message, line: 0, .. }) => Err(Error::runtime_error_at(paren, &message)),
+            result => result,
+        }
+    }
+
+    /// Run the method `instance` overloads `operator` with, eg. "a + b"
+    /// calls "a.__add__(b)". None when its class doesn't have one, so
+    /// the operator is applied as usual
+    fn call_operator_method(&mut self, instance: &Rc<Instance>, operator: &Token, right: &Object) -> Option<CblResult<Object>> {
+        let name = operator_method(&operator.type_)?;
+        let method = instance.class.find_method(name)?.bind(Object::Instance(instance.clone()));
+        let result = self.call(operator, &method, vec![right.clone()]);
+
+        // "a != b" is the opposite of "a.__eq__(b)"
+        match operator.type_ {
+            TokenType::BangEqual => Some(result.map(|value| Object::Bool(!self.is_truthy(&value)))),
+            _ => Some(result),
+        }
+    }
+
+    /// Resolve and then run a program. Globals it defines are still
+    /// there for the next program run by the same interpreter, call
+    /// `reset` in between to start each one from scratch
+    pub fn interpret(&mut self, statements: &[Stmt]) -> CblResult<()> {
+        Resolver::new(self).resolve(statements)?;
+
+        for statement in statements {
+            self.execute(statement)?;
+        }
+
+        Ok(())
+    }
+
+    /// Like `interpret`, but when the last statement is a bare expression
+    /// its value is returned, eg. "1 + 1;" gives back 2
+    pub fn interpret_value(&mut self, statements: &[Stmt]) -> CblResult<Option<Object>> {
+        Resolver::new(self).resolve(statements)?;
+
+        let (last, rest) = match statements.split_last() {
+            Some((Stmt::Expression { expression, .. }, rest)) => (Some(expression), rest),
+            _ => (None, statements),
+        };
+
+        for statement in rest {
+            self.execute(statement)?;
+        }
+
+        match last {
+            Some(expression) => self.evaluate(expression).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Apply an arithmetic operator. Two ints give an int as long as the
+/// result fits, otherwise the operands are converted to floats. None
+/// when either operand isn't a number
+fn arithmetic(
+    l: &Object,
+    r: &Object,
+    int_op: fn(i64, i64) -> Option<i64>,
+    float_op: fn(f64, f64) -> f64,
+) -> Option<Object> {
+    if let (Object::Int(l), Object::Int(r)) = (l, r) {
+        if let Some(n) = int_op(*l, *r) {
+            return Some(Object::Int(n));
+        }
+    }
+    Some(Object::Number(float_op(l.as_number()?, r.as_number()?)))
+}
+
+/// Apply a bitwise operator to two integer valued numbers, eg. 6 or 6.0
+/// but not 6.5. None when either operand isn't one, or `op` fails
+fn bitwise(l: &Object, r: &Object, op: fn(i64, i64) -> Option<i64>) -> Option<Object> {
+    fn integer(value: &Object) -> Option<i64> {
+        match value {
+            Object::Int(n) => Some(*n),
+            Object::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Some(*n as i64),
+            _ => None,
+        }
+    }
+
+    op(integer(l)?, integer(r)?).map(Object::Int)
+}
+
+/// Order two numbers or two strings, None when they can't be compared.
+/// The inner value is None when a NaN is involved
+fn compare(l: &Object, r: &Object) -> Option<Option<Ordering>> {
+    match (l, r) {
+        (Object::String(l), Object::String(r)) => Some(Some(l.cmp(r))),
+        (Object::Int(l), Object::Int(r)) => Some(Some(l.cmp(r))),
+        _ => Some(l.as_number()?.partial_cmp(&r.as_number()?)),
+    }
+}
+
+/// Read `object.name`, a field or method of an instance or one of the
+/// built in properties of a string
+fn get_property(name: &Token, object: &Object) -> CblResult<Object> {
+    match object {
+        Object::Instance(instance) => Instance::get(instance, name),
+        Object::String(s) => string_property(s, &name.lexeme).ok_or_else(|| {
+            Error::runtime_error_at(name, &format!("Undefined property '{}' on string.", name.lexeme))
+        }),
+        other => Err(Error::runtime_error_at(name, &format!("Only instances and strings have properties, got {}.", other.kind()))),
+    }
+}
+
+/// Set `object.name = value`, only instances have fields
+fn set_property(name: &Token, object: &Object, value: Object) -> CblResult<()> {
+    match object {
+        Object::Instance(instance) => {
+            instance.set(name, value);
+            Ok(())
+        }
+        other => Err(Error::runtime_error_at(name, &format!("Only instances have fields, got {}.", other.kind()))),
+    }
+}
+
+/// Read `object[index]`, a missing key in a map is nil rather than an error
+fn get_index(bracket: &Token, object: &Object, index: &Object) -> CblResult<Object> {
+    match object {
+        Object::Array(elements) => {
+            let elements = elements.borrow();
+            let i = array_index(bracket, index, elements.len())?;
+            Ok(elements[i].clone())
+        }
+        Object::Map(entries) => {
+            let key = map_key(bracket, index)?;
+            Ok(entries.borrow().get(key).cloned().unwrap_or(Object::Nil))
+        }
+        _ => Err(Error::runtime_error_at(bracket, "Can only index arrays and maps.")),
+    }
+}
+
+/// `object[index] = value`, arrays can only have existing elements set
+fn set_index(bracket: &Token, object: &Object, index: &Object, value: Object) -> CblResult<()> {
+    match object {
+        Object::Array(elements) => {
+            let mut elements = elements.borrow_mut();
+            let i = array_index(bracket, index, elements.len())?;
+            elements[i] = value;
+        }
+        Object::Map(entries) => {
+            let key = map_key(bracket, index)?;
+            entries.borrow_mut().insert(key.to_string(), value);
+        }
+        _ => return Err(Error::runtime_error_at(bracket, "Can only index arrays and maps.")),
+    }
+    Ok(())
+}
+
+/// Check `index` is a whole number that is in bounds for an array of
+/// length `len`, and convert it to a usize
+fn array_index(bracket: &Token, index: &Object, len: usize) -> CblResult<usize> {
+    let i = match index {
+        Object::Int(i) => *i,
+        Object::Number(n) if n.fract() == 0.0 => *n as i64,
+        _ => return Err(Error::runtime_error_at(bracket, &format!("Array index must be a whole number, got {}.", index))),
+    };
+
+    usize::try_from(i)
+        .ok()
+        .filter(|i| *i < len)
+        .ok_or_else(|| Error::runtime_error_at(bracket, &format!("Index {} is out of bounds for an array of length {}.", i, len)))
+}
+
+fn map_key<'a>(token: &Token, key: &'a Object) -> CblResult<&'a str> {
+    match key {
+        Object::String(key) => Ok(key),
+        _ => Err(Error::runtime_error_at(token, &format!("Map keys must be strings, got {}.", key))),
+    }
+}
+
+/// The method a class defines to overload a binary operator, eg.
+/// "__add__" for "+". "!=" uses "__eq__" and negates it, see the readme
+/// for the full list. Only the left operand's class is asked, so
+/// "1 + v" is still an error
+fn operator_method(operator: &TokenType) -> Option<&'static str> {
+    match operator {
+        TokenType::Plus => Some("__add__"),
+        TokenType::Minus => Some("__sub__"),
+        TokenType::Star => Some("__mul__"),
+        TokenType::Slash => Some("__div__"),
+        TokenType::Percent => Some("__mod__"),
+        TokenType::StarStar => Some("__pow__"),
+        TokenType::EqualEqual | TokenType::BangEqual => Some("__eq__"),
+        TokenType::Less => Some("__lt__"),
+        TokenType::LessEqual => Some("__le__"),
+        TokenType::Greater => Some("__gt__"),
+        TokenType::GreaterEqual => Some("__ge__"),
+        _ => None,
+    }
+}
+
+fn check_arity(paren: &Token, callable: &dyn Callable, got: usize) -> CblResult<()> {
+    let expected = callable.arity();
+    if callable.is_variadic() && got < expected {
+        return Err(Error::runtime_error_at(paren, &format!("Expected at least {} arguments but got {}.", expected, got)));
+    }
+    if !callable.is_variadic() && expected != got {
+        return Err(Error::runtime_error_at(paren, &format!("Expected {} arguments but got {}.", expected, got)));
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{scanner::Scanner, parser::Parser};
+    use crate::{scanner::Scanner, parser::Parser, runner::with_large_stack};
 
     use super::*;
 
+    fn interpret_source(source: &str) -> CblResult<Interpreter> {
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens()?;
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse()?;
+
+        let mut interpreter = Interpreter::new().with_captured_output();
+        interpreter.interpret(&statements)?;
+        Ok(interpreter)
+    }
+
+    fn get_var(interpreter: &Interpreter, name: &str) -> CblResult<Object> {
+        let token = Token::new(TokenType::Identifier, name.to_string(), Object::Nil, 1, 1);
+        interpreter.globals.borrow().get(&token)
+    }
+
     #[test]
     fn test_interpreter_1() {
-        let source = "-17.89 * 391.2";
+        let source = "var result = -17.89 * 391.2;";
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
     
         let mut parser = Parser::new(tokens.clone());
-        let expression = parser.parse().unwrap();
+        let statements = parser.parse().unwrap();
 
-        let interpreter = Interpreter::new();
-        let result = interpreter.interpret(&expression).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(&statements).unwrap();
+        let result = get_var(&interpreter, "result").unwrap();
         assert_eq!(result, Object::Number(-6998.568_f64));
     }
 
     #[test]
     fn test_interpreter_2() {
-        let source = "\"chess\" + \"rules\"";
+        let source = "var result = \"chess\" + \"rules\";";
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
     
         let mut parser = Parser::new(tokens.clone());
-        let expression = parser.parse().unwrap();
+        let statements = parser.parse().unwrap();
 
-        let interpreter = Interpreter::new();
-        let result = interpreter.interpret(&expression).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(&statements).unwrap();
+        let result = get_var(&interpreter, "result").unwrap();
         assert_eq!(result, Object::String("chessrules".to_string()));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_var_declaration() {
+        let source = "var a = 2; var b; var c = a + 1;";
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(&statements).unwrap();
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Nil);
+        assert_eq!(get_var(&interpreter, "c").unwrap(), Object::Int(3));
+    }
+
+    #[test]
+    fn test_undefined_variable() {
+        let source = "print a;";
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        assert!(interpreter.interpret(&statements).is_err());
+    }
+
+    #[test]
+    fn test_assignment() {
+        let source = "var a = 1; a = a + 4;";
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(&statements).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Int(5));
+    }
+
+    #[test]
+    fn test_assign_undeclared_variable() {
+        let source = "a = 1;";
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        assert!(interpreter.interpret(&statements).is_err());
+    }
+
+    #[test]
+    fn test_const() {
+        let interpreter = interpret_source("const PI = 3.5; var area = PI * 2 * 2; { const N = 1; print N; }").unwrap();
+        assert_eq!(get_var(&interpreter, "PI").unwrap(), Object::Number(3.5));
+        assert_eq!(interpreter.output(), ["1"]);
+
+        for source in ["const PI = 3.5; PI = 3;", "{ const N = 1; N += 1; }", "const N = 1; fun f() { N++; } f();"] {
+            match interpret_source(source) {
+                Err(Error::RuntimeError { message, .. }) => assert!(message.starts_with("Cannot assign to constant"), "{}", message),
+                _ => panic!("expected a runtime error from {}", source),
+            }
+        }
+
+        // redeclaring a global with var makes it assignable again
+        assert!(interpret_source("const a = 1; var a = 2; a = 3;").is_ok());
+    }
+
+    #[test]
+    fn test_block_scope() {
+        let source = "var a = 1; var b; { var a = 2; b = a; }";
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(&statements).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Int(1));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Int(2));
+    }
+
+    #[test]
+    fn test_block_scope_does_not_leak() {
+        let source = "{ var inner = 1; }";
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(&statements).unwrap();
+        assert!(get_var(&interpreter, "inner").is_err());
+    }
+
+    #[test]
+    fn test_if_else() {
+        let source = "var r; if (1 < 2) r = \"yes\"; else r = \"no\";";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "r").unwrap(), Object::String("yes".to_string()));
+
+        let source = "var r; if (2 < 1) r = \"yes\"; else r = \"no\";";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "r").unwrap(), Object::String("no".to_string()));
+    }
+
+    #[test]
+    fn test_if_truthiness() {
+        let source = "var r = 0; if (nil) r = 1; if (0) r = r + 2; if (\"\") r = r + 4;";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "r").unwrap(), Object::Int(6));
+    }
+
+    #[test]
+    fn test_while_loop() {
+        let source = "var i = 0; var sum = 0; while (i < 3) { sum = sum + i; i = i + 1; }";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "i").unwrap(), Object::Int(3));
+        assert_eq!(get_var(&interpreter, "sum").unwrap(), Object::Int(3));
+    }
+
+    #[test]
+    fn test_for_loop() {
+        let source = "var sum = 0; for (var i = 0; i < 3; i = i + 1) sum = sum + i;";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "sum").unwrap(), Object::Int(3));
+        assert!(get_var(&interpreter, "i").is_err());
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let source = "var a = nil or 2; var b = 1 and 3; var c = false and 1;";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Int(2));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Int(3));
+        assert_eq!(get_var(&interpreter, "c").unwrap(), Object::Bool(false));
+    }
+
+    #[test]
+    fn test_logical_short_circuit() {
+        let source = "var a = false and undefined_var; var b = true or undefined_var;";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Bool(false));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Bool(true));
+    }
+
+    #[test]
+    fn test_nil_coalesce() {
+        let source = "var a = nil ?? 5; var b = 3 ?? 5; var c = false ?? 5; var d = 1 ?? undefined_var;";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Int(5));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Int(3));
+        assert_eq!(get_var(&interpreter, "c").unwrap(), Object::Bool(false));
+        assert_eq!(get_var(&interpreter, "d").unwrap(), Object::Int(1));
+    }
+
+    #[test]
+    fn test_modulo() {
+        let source = "var a = 7 % 3; var b = 2 + 7 % 3 * 2;";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Int(1));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Int(4));
+    }
+
+    #[test]
+    fn test_modulo_by_zero() {
+        assert!(interpret_source("var a = 7 % 0;").is_err());
+    }
+
+    #[test]
+    fn test_runtime_error_line() {
+        match interpret_source("var a = 1;\n\"a\" - 1;") {
+            Err(Error::RuntimeError { line, .. }) => assert_eq!(line, 2),
+            _ => panic!("Expected a runtime error."),
+        }
+    }
+
+    #[test]
+    fn test_string_concatenation_coercion() {
+        let source = "var a = \"n=\" + 5; var b = 5 + \"!\"; var c = \"is \" + true; var d = 1 + 2;";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::String("n=5".to_string()));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::String("5!".to_string()));
+        assert_eq!(get_var(&interpreter, "c").unwrap(), Object::String("is true".to_string()));
+        assert_eq!(get_var(&interpreter, "d").unwrap(), Object::Int(3));
+    }
+
+    #[test]
+    fn test_captured_output() {
+        let interpreter = interpret_source("print 1; print 2;").unwrap();
+        assert_eq!(interpreter.output(), ["1", "2"]);
+    }
+
+    #[test]
+    fn test_with_writer() {
+        /// A `Vec<u8>` the test can still read after handing it over
+        #[derive(Clone, Default)]
+        struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+        impl Write for SharedBuffer {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::new().with_writer(buffer.clone());
+        let tokens = Scanner::new("print 1 + 2; print \"a\";").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        interpreter.interpret(&statements).unwrap();
+
+        assert_eq!(buffer.0.borrow().as_slice(), b"3\na\n");
+    }
+
+    #[test]
+    fn test_print_shadowed_variable() {
+        let interpreter = interpret_source("var a = 1; { var a = 2; print a; } print a;").unwrap();
+        assert_eq!(interpreter.output(), ["2", "1"]);
+    }
+
+    #[test]
+    fn test_interpret_value() {
+        let mut scanner = Scanner::new("var a = 1; a + 1;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        let result = interpreter.interpret_value(&statements).unwrap();
+        assert_eq!(result, Some(Object::Int(2)));
+    }
+
+    #[test]
+    fn test_interpret_value_without_expression() {
+        let mut scanner = Scanner::new("1 + 1; var a = 1;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.interpret_value(&statements).unwrap(), None);
+    }
+
+    #[test]
+    fn test_string_comparison() {
+        let source = "var a = \"a\" < \"b\"; var b = \"b\" < \"a\"; var c = \"apple\" <= \"apple\";";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Bool(true));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Bool(false));
+        assert_eq!(get_var(&interpreter, "c").unwrap(), Object::Bool(true));
+    }
+
+    #[test]
+    fn test_mixed_comparison_is_an_error() {
+        assert!(interpret_source("\"a\" < 1;").is_err());
+    }
+
+    #[test]
+    fn test_float_tolerance() {
+        let mut scanner = Scanner::new("0.1 + 0.2 == 0.3;");
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.interpret_value(&statements).unwrap(), Some(Object::Bool(false)));
+
+        let mut interpreter = Interpreter::new().with_float_tolerance(1e-9);
+        assert_eq!(interpreter.interpret_value(&statements).unwrap(), Some(Object::Bool(true)));
+    }
+
+    #[test]
+    fn test_equality_edge_cases() {
+        let source = "
+            var inf = 1e308 * 10;
+            var nan = inf - inf;
+            var a = nil == nil;
+            var b = nan == nan;
+            var c = nan != nan;
+            var d = -0.0 == 0.0;
+            var e = -0 == 0;
+            var f = inf == 1e308 * 10;
+            var g = nil == false;
+        ";
+        let interpreter = interpret_source(source).unwrap();
+        for (name, expected) in [("a", true), ("b", false), ("c", true), ("d", true), ("e", true), ("f", true), ("g", false)] {
+            assert_eq!(get_var(&interpreter, name).unwrap(), Object::Bool(expected), "{}", name);
+        }
+
+        // the tolerance doesn't change any of that
+        let tokens = Scanner::new("var inf = 1e308 * 10; var nan = inf - inf; nan == nan;").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new().with_float_tolerance(1e-9);
+        assert_eq!(interpreter.interpret_value(&statements).unwrap(), Some(Object::Bool(false)));
+
+        let tokens = Scanner::new("inf == inf;").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        assert_eq!(interpreter.interpret_value(&statements).unwrap(), Some(Object::Bool(true)));
+    }
+
+    #[test]
+    fn test_exponent() {
+        let source = "var a = 2 ** 3 ** 2; var b = 2 ** -1; var c = -2 ** 2;";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Int(512));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Number(0.5));
+        assert_eq!(get_var(&interpreter, "c").unwrap(), Object::Int(-4));
+    }
+
+    #[test]
+    fn test_print_number_formatting() {
+        let source = "print 3.0; print 3.5; print 1e21; print 0.000000123; print -0.25; print 100;";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["3", "3.5", "1e21", "1.23e-7", "-0.25", "100"]);
+    }
+
+    #[test]
+    fn test_clock() {
+        let interpreter = interpret_source("var start = clock(); var elapsed = clock() - start;").unwrap();
+        match get_var(&interpreter, "start").unwrap() {
+            Object::Number(n) => assert!(n > 0.0),
+            other => panic!("clock() returned {:?}", other),
+        }
+        match get_var(&interpreter, "elapsed").unwrap() {
+            Object::Number(n) => assert!(n >= 0.0),
+            other => panic!("clock() returned {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_call_errors() {
+        match interpret_source("\"not a function\"();") {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Can only call functions and classes."),
+            _ => panic!("expected a runtime error"),
+        }
+        match interpret_source("clock(1);") {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Expected 0 arguments but got 1."),
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn test_return_from_loop() {
+        let source = "
+            fun firstOver(limit) {
+                for (var i = 0; i < 100; i = i + 1) {
+                    if (i * i > limit) return i;
+                }
+                return;
+            }
+            var a = firstOver(10);
+            var b = firstOver(100000);
+        ";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Int(4));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Nil);
+    }
+
+    #[test]
+    fn test_callables_share_dispatch() {
+        /// A callable registered from outside, like an embedder would
+        #[derive(Debug)]
+        struct Twice;
+
+        impl Callable for Twice {
+            fn name(&self) -> &str {
+                "twice"
+            }
+
+            fn arity(&self) -> usize {
+                1
+            }
+
+            fn call(&self, _interpreter: &mut Interpreter, arguments: Vec<Object>) -> CblResult<Object> {
+                Ok(Object::Int(arguments[0].as_number().unwrap_or_default() as i64 * 2))
+            }
+        }
+
+        let run = |source: &str| {
+            let tokens = Scanner::new(source).scan_tokens()?;
+            let statements = Parser::new(tokens).parse()?;
+            let mut interpreter = Interpreter::new();
+            interpreter.globals.borrow_mut().define("twice", Object::Callable(Rc::new(Twice)));
+            interpreter.interpret(&statements).map(|_| interpreter)
+        };
+
+        let interpreter = run("fun add(a, b) { return a + b; } var a = twice(add(1, 2)); var b = add(twice(1), 1);").unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Int(6));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Int(3));
+
+        // arity is checked the same way for both
+        for (source, message) in [("twice();", "Expected 1 arguments but got 0."), ("fun f(a) {} f();", "Expected 1 arguments but got 0.")] {
+            match run(source) {
+                Err(Error::RuntimeError { message: m, .. }) => assert_eq!(m, message),
+                _ => panic!("expected an arity error from {}", source),
+            }
+        }
+    }
+
+    #[test]
+    fn test_function_without_return() {
+        let source = "fun greet(name) { print \"hi \" + name; } var result = greet(\"bob\");";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["hi bob"]);
+        assert_eq!(get_var(&interpreter, "result").unwrap(), Object::Nil);
+    }
+
+    #[test]
+    fn test_closure_counter() {
+        let source = "
+            fun makeCounter() {
+                var i = 0;
+                fun count() {
+                    i = i + 1;
+                    print i;
+                }
+                return count;
+            }
+
+            var counter = makeCounter();
+            counter();
+            counter();
+        ";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["1", "2"]);
+    }
+
+    #[test]
+    fn test_is_truthy() {
+        let interpreter = Interpreter::new();
+        assert!(!interpreter.is_truthy(&Object::Nil));
+        assert!(!interpreter.is_truthy(&Object::Bool(false)));
+        assert!(interpreter.is_truthy(&Object::Bool(true)));
+        assert!(interpreter.is_truthy(&Object::Int(0)));
+        assert!(interpreter.is_truthy(&Object::Number(0.0)));
+        assert!(interpreter.is_truthy(&Object::Number(-1.5)));
+        assert!(interpreter.is_truthy(&Object::String(String::new())));
+        assert!(interpreter.is_truthy(&Object::String("false".to_string())));
+
+        let clock = get_var(&interpreter, "clock").unwrap();
+        assert!(interpreter.is_truthy(&clock));
+
+        let interpreter = interpret_source("fun f() {}").unwrap();
+        assert!(interpreter.is_truthy(&get_var(&interpreter, "f").unwrap()));
+    }
+
+    #[test]
+    fn test_conditions_use_truthiness() {
+        let source = "if (0) print \"zero\"; if (\"\") print \"empty\"; if (nil) print \"nil\"; print nil or \"default\";";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["zero", "empty", "default"]);
+    }
+
+    #[test]
+    fn test_unary_plus() {
+        let interpreter = interpret_source("var a = +\"3.5\"; var b = +7; var c = +\"2\" + 1;").unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Number(3.5));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Int(7));
+        assert_eq!(get_var(&interpreter, "c").unwrap(), Object::Int(3));
+
+        match interpret_source("+\"abc\";") {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Cannot convert \"abc\" to a number."),
+            _ => panic!("expected a runtime error"),
+        }
+        assert!(interpret_source("+nil;").is_err());
+    }
+
+    #[test]
+    fn test_int_arithmetic() {
+        let source = "var a = 7 + 3 * 2; var b = 7 % 3; var c = 2 ** 10; var d = -5; var e = 9223372036854775807 + 1;";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Int(13));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Int(1));
+        assert_eq!(get_var(&interpreter, "c").unwrap(), Object::Int(1024));
+        assert_eq!(get_var(&interpreter, "d").unwrap(), Object::Int(-5));
+        // overflowing falls back to a float
+        assert_eq!(get_var(&interpreter, "e").unwrap(), Object::Number(9223372036854775808.0));
+    }
+
+    #[test]
+    fn test_mixed_int_float_arithmetic() {
+        let source = "var a = 1 + 0.5; var b = 2 * 1.5; var c = 2 ** -1; var d = 1 == 1.0; var e = 2 < 2.5;";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Number(1.5));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Number(3.0));
+        assert_eq!(get_var(&interpreter, "c").unwrap(), Object::Number(0.5));
+        assert_eq!(get_var(&interpreter, "d").unwrap(), Object::Bool(true));
+        assert_eq!(get_var(&interpreter, "e").unwrap(), Object::Bool(true));
+    }
+
+    #[test]
+    fn test_division_gives_float() {
+        let interpreter = interpret_source("var a = 5 / 2; var b = 4 / 2; print a; print b;").unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Number(2.5));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Number(2.0));
+        assert_eq!(interpreter.output(), ["2.5", "2"]);
+
+        assert!(interpret_source("5 % 0;").is_err());
+    }
+
+    #[test]
+    fn test_assert() {
+        assert!(interpret_source("assert 1 == 1; assert \"non-empty\", \"unused\";").is_ok());
+
+        match interpret_source("var a = 1;\nassert a == 2;") {
+            Err(Error::RuntimeError { message, line, column }) => {
+                assert_eq!(message, "Assertion failed.");
+                assert_eq!((line, column), (2, 1));
+            }
+            _ => panic!("expected a runtime error"),
+        }
+        match interpret_source("var a = 1; assert a == 2, \"a is \" + a;") {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Assertion failed: a is 1"),
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let parse = |source: &str| Parser::new(Scanner::new(source).scan_tokens().unwrap()).parse().unwrap();
+        let a = Token::new(TokenType::Identifier, "a".to_string(), Object::Nil, 1, 1);
+        let mut interpreter = Interpreter::new().with_captured_output();
+
+        // without a reset the second program sees the first one's globals
+        interpreter.interpret(&parse("var a = 1;")).unwrap();
+        interpreter.interpret(&parse("a = a + 1;")).unwrap();
+        assert_eq!(interpreter.globals().get(&a).unwrap(), Object::Int(2));
+
+        interpreter.reset();
+        assert!(interpreter.globals().get(&a).is_err());
+        assert!(interpreter.interpret(&parse("print a;")).is_err());
+        interpreter.interpret(&parse("print len(\"abc\");")).unwrap();
+        assert_eq!(interpreter.output(), ["3"]);
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        for source in ["1 / 0;", "print 0 / 0.0;", "var a = 1;\na /= -0.0;"] {
+            match interpret_source(source) {
+                Err(Error::RuntimeError { message, line, .. }) => {
+                    assert_eq!(message, "Division by zero.");
+                    assert_eq!(line, source.lines().count() as u32);
+                }
+                _ => panic!("expected a runtime error for {}", source),
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_quoted_string() {
+        let interpreter = interpret_source("var a = 'hi' + \"!\";").unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::String("hi!".to_string()));
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let source = "var x = 2; print \"x=${x}\"; print 'sum: ${x + 1}, nested: ${\"<${x * 2}>\"}!';";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["x=2", "sum: 3, nested: <4>!"]);
+    }
+
+    #[test]
+    fn test_runaway_recursion_is_an_error() {
+        with_large_stack(|| {
+            match interpret_source("fun forever(n) { return forever(n + 1); } forever(0);") {
+                Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Stack overflow."),
+                _ => panic!("expected a runtime error"),
+            }
+
+            // depth(n) makes n + 1 calls, so the last of these is right at the limit
+            let depth = "fun depth(n) { if (n > 0) return depth(n - 1) + 1; return 0; }";
+            for n in [150, DEFAULT_MAX_CALL_DEPTH - 1] {
+                let interpreter = interpret_source(&format!("{} var d = depth({});", depth, n)).unwrap();
+                assert_eq!(get_var(&interpreter, "d").unwrap(), Object::Int(n as i64));
+            }
+            assert!(interpret_source(&format!("{} depth({});", depth, DEFAULT_MAX_CALL_DEPTH)).is_err());
+        });
+
+        let tokens = Scanner::new("fun f(n) { if (n > 0) f(n - 1); } f(10);").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new().with_max_call_depth(10);
+        assert!(interpreter.interpret(&statements).is_err());
+    }
+
+    #[test]
+    fn test_string_properties() {
+        let source = "print \"Hi\".lower(); print \"Hi\".upper(); print \"  a b \".trim(); print \"héllo\".length; var f = \"Ab\".lower; print f();";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["hi", "HI", "a b", "5", "ab"]);
+
+        match interpret_source("\"Hi\".shout();") {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Undefined property 'shout' on string."),
+            _ => panic!("expected a runtime error"),
+        }
+        match interpret_source("var n = 1; n.length;") {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Only instances and strings have properties, got number."),
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn test_classes() {
+        let source = "
+            class Point {
+                init(x, y) { this.x = x; this.y = y; }
+                move(dx) { this.x = this.x + dx; return this; }
+                sum() { return this.x + this.y; }
+            }
+            var p = Point(1, 2);
+            print p.move(10).sum();
+            p.y++;
+            p.label = \"p\";
+            print p.y;
+            print p.label;
+            print p;
+            print Point;
+            var m = p.sum;
+            p.x = 0;
+            print m();
+            print p.init(5, 5) == p;
+        ";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["13", "3", "p", "<Point instance>", "<class Point>", "3", "true"]);
+
+        match interpret_source("class A {} A().missing;") {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Undefined property 'missing'."),
+            _ => panic!("expected a runtime error"),
+        }
+        match interpret_source("var s = \"s\"; s.x = 1;") {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Only instances have fields, got string."),
+            _ => panic!("expected a runtime error"),
+        }
+        match interpret_source("class A { init(a) {} } A();") {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Expected 1 arguments but got 0."),
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn test_operator_overloading() {
+        let source = "
+            class Vector {
+                init(x, y) { this.x = x; this.y = y; }
+                __add__(other) { return Vector(this.x + other.x, this.y + other.y); }
+                __eq__(other) { return this.x == other.x and this.y == other.y; }
+                __lt__(other) { return this.x < other.x; }
+            }
+            var v = Vector(1, 2) + Vector(3, 4);
+            print v.x; print v.y;
+            print v == Vector(4, 6); print v != Vector(4, 6); print Vector(0, 0) < v;
+        ";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["4", "6", "true", "false", "true"]);
+
+        // without the method an instance is an operand like any other
+        match interpret_source("class A {} A() - 1;") {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Expected numbers for Minus operation: Minus"),
+            _ => panic!("expected a runtime error"),
+        }
+        assert!(interpret_source("class A {} var a = A(); assert a == a;").is_ok());
+    }
+
+    #[test]
+    fn test_inheritance() {
+        let source = "
+            class Animal {
+                init(name) { this.name = name; }
+                speak() { return this.name + \" makes a sound\"; }
+                describe() { return \"I am \" + this.name; }
+            }
+            class Dog < Animal {
+                init(name) { super.init(name); this.tricks = 0; }
+                speak() { return super.speak() + \", woof\"; }
+            }
+            class Puppy < Dog {
+                speak() { return super.speak() + \"!\"; }
+            }
+            var d = Puppy(\"Rex\");
+            print d.speak();
+            print d.describe();
+            print d.tricks;
+        ";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["Rex makes a sound, woof!", "I am Rex", "0"]);
+
+        match interpret_source("var A = 1; class B < A {}") {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Superclass must be a class."),
+            _ => panic!("expected a runtime error"),
+        }
+        match interpret_source("class A {} class B < A { f() { return super.missing; } } B().f();") {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Undefined property 'missing'."),
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn test_for_each() {
+        let source = "
+            for (x in [10, 20]) print x;
+            var a = [1];
+            for (x in a) push(a, x);
+            print a;
+            var fns = [];
+            for (c in \"ab\") { fun f() { return c; } push(fns, f); }
+            print fns[0]() + fns[1]();
+            for (var i in [1, 2, 3, 4]) { if (i == 2) continue; if (i == 4) break; print i; }
+        ";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["10", "20", "[1, 1]", "ab", "1", "3"]);
+
+        match interpret_source("for (x in 5) print x;") {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Can only loop over arrays and strings, got number."),
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let statements = Parser::new(Scanner::new("var i = 0; while (true) { i = i + 1; }").scan_tokens().unwrap())
+            .parse()
+            .unwrap();
+        let mut interpreter = Interpreter::new().with_captured_output().with_step_limit(1000);
+        match interpreter.interpret(&statements) {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Step limit exceeded."),
+            _ => panic!("expected a runtime error"),
+        }
+
+        // the error points at the statement that hit the limit
+        let statements = Parser::new(Scanner::new("var i = 0;\nwhile (true) print 1;").scan_tokens().unwrap())
+            .parse()
+            .unwrap();
+        let mut interpreter = Interpreter::new().with_captured_output().with_step_limit(10);
+        match interpreter.interpret(&statements) {
+            Err(Error::RuntimeError { line, .. }) => assert_eq!(line, 2),
+            _ => panic!("expected a runtime error"),
+        }
+
+        // a program that finishes within the limit runs as normal
+        let statements = Parser::new(Scanner::new("for (var i = 0; i < 10; i = i + 1) {}").scan_tokens().unwrap())
+            .parse()
+            .unwrap();
+        assert!(Interpreter::new().with_step_limit(1000).interpret(&statements).is_ok());
+    }
+
+    #[test]
+    fn test_on_statement() {
+        let source = "var a = 1;\nprint a;\n\na = a + 1;\nprint 1;\nif\n(a > 1) print 2;\nwhile\n(a < 3)\na = a + 1;";
+        let statements = Parser::new(Scanner::new(source).scan_tokens().unwrap()).parse().unwrap();
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let seen = lines.clone();
+        let mut interpreter = Interpreter::new()
+            .with_captured_output()
+            .with_on_statement(move |line| seen.borrow_mut().push(line));
+        interpreter.interpret(&statements).unwrap();
+        assert_eq!(*lines.borrow(), [1, 2, 4, 5, 6, 7, 8, 10]);
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let source = "for (var i = 0; i < 5; i = i + 1) { if (i == 2) continue; if (i == 4) break; print i; }";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["0", "1", "3"]);
+
+        let source = "var i = 0; while (true) { i = i + 1; if (i < 3) continue; break; } print i;";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["3"]);
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let source = "var a = 6 & 3; var b = 1 << 4; var c = 6 | 3; var d = 6 ^ 3; var e = -16 >> 2; var f = 4.0 & 5; var g = 1 | 2 == 3;";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Int(2));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::Int(16));
+        assert_eq!(get_var(&interpreter, "c").unwrap(), Object::Int(7));
+        assert_eq!(get_var(&interpreter, "d").unwrap(), Object::Int(5));
+        assert_eq!(get_var(&interpreter, "e").unwrap(), Object::Int(-4));
+        assert_eq!(get_var(&interpreter, "f").unwrap(), Object::Int(4));
+        assert_eq!(get_var(&interpreter, "g").unwrap(), Object::Bool(true));
+
+        assert!(interpret_source("var a = 1.5 & 1;").is_err());
+        assert!(interpret_source("var a = \"a\" | 1;").is_err());
+        assert!(interpret_source("var a = 1 << 64;").is_err());
+        assert!(interpret_source("var a = 1 << -1;").is_err());
+    }
+
+    #[test]
+    fn test_arrays() {
+        let interpreter = interpret_source("var a = [1, 2, 3]; print a[1]; var b = a; print b; print [[\"x\"], []][0][0];").unwrap();
+        assert_eq!(interpreter.output(), ["2", "[1, 2, 3]", "x"]);
+        assert_eq!(get_var(&interpreter, "a").unwrap(), get_var(&interpreter, "b").unwrap());
+
+        let interpreter = interpret_source("var a = [1] == [1]; var b = [1 + 1, \"a\"][1.0];").unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Bool(false));
+        assert_eq!(get_var(&interpreter, "b").unwrap(), Object::String("a".to_string()));
+    }
+
+    #[test]
+    fn test_array_index_errors() {
+        for (source, expected) in [
+            ("[1, 2, 3][3];", "Index 3 is out of bounds for an array of length 3."),
+            ("[1, 2, 3][-1];", "Index -1 is out of bounds for an array of length 3."),
+            ("[1][0.5];", "Array index must be a whole number, got 0.5."),
+            ("1[0];", "Can only index arrays and maps."),
+        ] {
+            match interpret_source(source) {
+                Err(Error::RuntimeError { message, .. }) => assert_eq!(message, expected),
+                _ => panic!("expected a runtime error from {}", source),
+            }
+        }
+    }
+
+    #[test]
+    fn test_maps() {
+        let source = "
+            var m = {\"a\": 1, \"b\" + \"c\": [2]};
+            print m[\"a\"];
+            print m[\"missing\"];
+            m[\"d\"] = \"x\";
+            m[\"a\"] = m[\"a\"] + 1;
+            print m;
+            var empty = {};
+            print empty;
+        ";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["1", "nil", "{\"a\": 2, \"bc\": [2], \"d\": \"x\"}", "{}"]);
+
+        assert!(interpret_source("var m = {1: 2};").is_err());
+        assert!(interpret_source("var m = {}; m[1];").is_err());
+    }
+
+    #[test]
+    fn test_index_assign() {
+        let interpreter = interpret_source("var a = [1, 2]; var b = a; b[0] = 5; print a; a[1] = a; print a;").unwrap();
+        assert_eq!(interpreter.output(), ["[5, 2]", "[5, [...]]"]);
+        assert!(interpret_source("var a = [1]; a[1] = 2;").is_err());
+    }
+
+    #[test]
+    fn test_collection_natives() {
+        let interpreter = interpret_source("var a = [1]; push(a, 2); print len(a); print pop(a); print a; print len(\"héllo\"); print len({\"a\": 1});").unwrap();
+        assert_eq!(interpreter.output(), ["2", "2", "[1]", "5", "1"]);
+
+        for (source, expected) in [
+            ("pop([]);", "Can't pop from an empty array."),
+            ("len(1);", "len() expects a string, array or map, got number."),
+            ("push(\"a\", 1);", "push() expects an array, got string."),
+        ] {
+            match interpret_source(&format!("\n{}", source)) {
+                Err(Error::RuntimeError { message, line, .. }) => {
+                    assert_eq!(message, expected);
+                    assert_eq!(line, 2);
+                }
+                _ => panic!("expected a runtime error from {}", source),
+            }
+        }
+    }
+
+    #[test]
+    fn test_type() {
+        let source = "class A {} print type(3); print type(1.5); print type(nil); print type(\"a\"); print type(true); print type([]); print type(clock); print type(A); print type(A());";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["number", "number", "nil", "string", "bool", "array", "function", "class", "instance"]);
+    }
+
+    #[test]
+    fn test_format() {
+        let source = "print format(\"{:.2}\", 3.14159); print format(\"{} + {} = {:.1}\", 1, \"one\", 2); print format(\"{{}}\"); print len(format(\"{:.100}\", 1));";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["3.14", "1 + one = 2.0", "{}", "102"]);
+
+        for (source, expected) in [
+            ("format();", "Expected at least 1 arguments but got 0."),
+            ("format(\"{}\");", "format() has more placeholders than arguments."),
+            ("format(\"\", 1);", "format() has more arguments than placeholders."),
+            ("format(\"{:x}\", 1);", "format() doesn't understand '{:x}'."),
+            ("format(\"{:.2}\", \"a\");", "format() can only set the precision of numbers, got string."),
+            ("format(\"{:.70000}\", 1.5);", "format() can't write more than 100 decimal places."),
+            ("format(\"{:.99999999999999999999}\", 1.5);", "format() doesn't understand '{:.99999999999999999999}'."),
+        ] {
+            match interpret_source(source) {
+                Err(Error::RuntimeError { message, .. }) => assert_eq!(message, expected),
+                _ => panic!("expected a runtime error from {}", source),
+            }
+        }
+    }
+
+    #[test]
+    fn test_not_uses_truthiness() {
+        let source = "var a = !nil; var b = !\"x\"; var c = !false; var d = !0; var e = !1 == false;";
+        let interpreter = interpret_source(source).unwrap();
+        for (name, expected) in [("a", true), ("b", false), ("c", true), ("d", false), ("e", true)] {
+            assert_eq!(get_var(&interpreter, name).unwrap(), Object::Bool(expected), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_print_expression() {
+        let interpreter = interpret_source("var x = print(\"hi\"); var y = print(1) + print(2); print(y);").unwrap();
+        assert_eq!(interpreter.output(), ["hi", "1", "2", "3"]);
+        assert_eq!(get_var(&interpreter, "x").unwrap(), Object::String("hi".to_string()));
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        let interpreter = interpret_source("var naïve = 3; print naïve;").unwrap();
+        assert_eq!(interpreter.output(), ["3"]);
+    }
+
+    #[test]
+    fn test_postfix_increment() {
+        let interpreter = interpret_source("var i = 1; print i++; print i; var j = i--; print j; print i;").unwrap();
+        assert_eq!(interpreter.output(), ["1", "2", "2", "1"]);
+
+        let source = "var a = [1.5]; a[0]++; var m = {\"n\": 0}; m[\"n\"]--; fun f() { var k = 0; k++; return k; }";
+        let interpreter = interpret_source(&format!("{} print a; print m; print f();", source)).unwrap();
+        assert_eq!(interpreter.output(), ["[2.5]", "{\"n\": -1}", "1"]);
+
+        assert!(interpret_source("var s = \"a\"; s++;").is_err());
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let source = "var x = 5; x *= 3; print x; x -= 1; x /= 2; print x; var s = \"a\"; s += \"x\"; print s; { var l = 1; l += 1; print l; }";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["15", "7", "ax", "2"]);
+    }
+
+    #[test]
+    fn test_compound_assignment_evaluates_target_once() {
+        let source = "
+            var calls = 0;
+            fun idx() { calls += 1; return 0; }
+            var a = [10];
+            a[idx()] += 1;
+            class Box {}
+            var boxes = [Box()];
+            boxes[0].n = 1;
+            boxes[idx()].n *= 5;
+            print a[0]; print boxes[0].n; print calls;
+        ";
+        let interpreter = interpret_source(source).unwrap();
+        assert_eq!(interpreter.output(), ["11", "5", "2"]);
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let mut interpreter = interpret_source("var a = 1; fun get() { return a; }").unwrap();
+        let snapshot = interpreter.snapshot();
+
+        let tokens = Scanner::new("var a = 2; var b = 3;").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        interpreter.interpret(&statements).unwrap();
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Int(2));
+
+        interpreter.restore(snapshot);
+        assert_eq!(get_var(&interpreter, "a").unwrap(), Object::Int(1));
+        assert!(get_var(&interpreter, "b").is_err());
+
+        let tokens = Scanner::new("get();").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        assert_eq!(interpreter.interpret_value(&statements).unwrap(), Some(Object::Int(1)));
+    }
+}
@@ -3,6 +3,14 @@ pub mod parser;
 pub mod scanner;
 pub mod token;
 pub mod error;  
+pub mod environment;
 pub mod interpreter;
+pub mod resolver;
+pub mod callable;
+pub mod function;
+pub mod class;
+pub mod native;
+pub mod repl;
+pub mod runner;
 
 pub mod wasm;
\ No newline at end of file
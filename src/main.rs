@@ -0,0 +1,24 @@
+use std::{env, process};
+
+use cbl_lib::{
+    repl::repl,
+    runner::{ast_json_file, check_file, dump_ast_file, run_file, time_file, tokens_file, EX_USAGE},
+};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    match args.as_slice() {
+        [_] => repl(),
+        [_, command, path] if command == "tokens" => process::exit(tokens_file(path)),
+        [_, flag, path] if flag == "--dump-ast" => process::exit(dump_ast_file(path)),
+        [_, flag, path] if flag == "--ast-json" => process::exit(ast_json_file(path)),
+        [_, flag, path] if flag == "--time" => process::exit(time_file(path)),
+        [_, flag, path] if flag == "--check" => process::exit(check_file(path)),
+        [_, path] => process::exit(run_file(path)),
+        _ => {
+            eprintln!("Usage: cbl [tokens | --dump-ast | --ast-json | --time | --check] [script]");
+            process::exit(EX_USAGE);
+        }
+    }
+}
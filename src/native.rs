@@ -0,0 +1,287 @@
+use std::{fmt::Debug, rc::Rc};
+
+use crate::{
+    callable::Callable,
+    environment::Environment,
+    error::{CblResult, Error},
+    interpreter::Interpreter,
+    token::Object,
+};
+
+/// A function implemented in Rust that scripts can call, eg. `clock()`
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    /// Whether it takes `arity` or more arguments, see `Callable::is_variadic`
+    pub variadic: bool,
+    pub function: fn(&[Object]) -> CblResult<Object>,
+}
+
+impl NativeFunction {
+    pub fn new(name: &str, arity: usize, function: fn(&[Object]) -> CblResult<Object>) -> Self {
+        NativeFunction {
+            name: name.to_string(),
+            arity,
+            variadic: false,
+            function,
+        }
+    }
+
+    /// A native taking at least `arity` arguments
+    pub fn variadic(name: &str, arity: usize, function: fn(&[Object]) -> CblResult<Object>) -> Self {
+        NativeFunction {
+            variadic: true,
+            ..NativeFunction::new(name, arity, function)
+        }
+    }
+}
+
+impl Callable for NativeFunction {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn is_variadic(&self) -> bool {
+        self.variadic
+    }
+
+    fn call(&self, _interpreter: &mut Interpreter, arguments: Vec<Object>) -> CblResult<Object> {
+        (self.function)(&arguments)
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+/// Define every native function in `environment`, normally the globals
+pub fn define_natives(environment: &mut Environment) {
+    let natives = [
+        NativeFunction::new("clock", 0, clock),
+        NativeFunction::new("len", 1, len),
+        NativeFunction::new("push", 2, push),
+        NativeFunction::new("pop", 1, pop),
+        NativeFunction::new("type", 1, type_of),
+        NativeFunction::variadic("format", 1, format),
+    ];
+
+    for native in natives {
+        let name = native.name.clone();
+        environment.define(&name, Object::Callable(Rc::new(native)));
+    }
+    environment.define("print", Object::Callable(Rc::new(Print)));
+}
+
+/// `print(value)`, the expression form of the `print` statement. It
+/// returns the value it printed so it can be used inside expressions
+#[derive(Debug)]
+struct Print;
+
+impl Callable for Print {
+    fn name(&self) -> &str {
+        "print"
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, mut arguments: Vec<Object>) -> CblResult<Object> {
+        let value = arguments.remove(0);
+        interpreter.print(&value)?;
+        Ok(value)
+    }
+}
+
+/// Look up a property of the string `s`: "length" is its number of
+/// characters and the methods "upper", "lower" and "trim" come back as
+/// functions bound to `s`. None for anything else
+pub(crate) fn string_property(s: &str, name: &str) -> Option<Object> {
+    let method: fn(&str) -> String = match name {
+        "length" => return Some(Object::Int(s.chars().count() as i64)),
+        "upper" => str::to_uppercase,
+        "lower" => str::to_lowercase,
+        "trim" => |s| s.trim().to_string(),
+        _ => return None,
+    };
+    Some(Object::Callable(Rc::new(StringMethod {
+        name: name.to_string(),
+        receiver: s.to_string(),
+        method,
+    })))
+}
+
+/// A string method bound to the string it was read from, eg. the
+/// "s.upper" in "s.upper()"
+struct StringMethod {
+    name: String,
+    receiver: String,
+    method: fn(&str) -> String,
+}
+
+impl Callable for StringMethod {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn call(&self, _interpreter: &mut Interpreter, _arguments: Vec<Object>) -> CblResult<Object> {
+        Ok(Object::String((self.method)(&self.receiver)))
+    }
+}
+
+impl Debug for StringMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+/// Seconds since the Unix epoch, mostly useful for timing scripts
+fn clock(_arguments: &[Object]) -> CblResult<Object> {
+    Ok(Object::Number(now()))
+}
+
+/// An error from a native, natives don't know where they were called
+/// from so the interpreter fills in the line of the call
+fn native_error(message: &str) -> Error {
+    Error::runtime_error(0, message)
+}
+
+/// Number of characters in a string, elements in an array or entries in a map
+fn len(arguments: &[Object]) -> CblResult<Object> {
+    let len = match &arguments[0] {
+        Object::String(s) => s.chars().count(),
+        Object::Array(elements) => elements.borrow().len(),
+        Object::Map(entries) => entries.borrow().len(),
+        other => return Err(native_error(&format!("len() expects a string, array or map, got {}.", other.kind()))),
+    };
+    Ok(Object::Int(len as i64))
+}
+
+/// Add a value to the end of an array
+fn push(arguments: &[Object]) -> CblResult<Object> {
+    match &arguments[0] {
+        Object::Array(elements) => {
+            elements.borrow_mut().push(arguments[1].clone());
+            Ok(Object::Nil)
+        }
+        other => Err(native_error(&format!("push() expects an array, got {}.", other.kind()))),
+    }
+}
+
+/// Remove and return the last value of an array
+fn pop(arguments: &[Object]) -> CblResult<Object> {
+    match &arguments[0] {
+        Object::Array(elements) => elements
+            .borrow_mut()
+            .pop()
+            .ok_or_else(|| native_error("Can't pop from an empty array.")),
+        other => Err(native_error(&format!("pop() expects an array, got {}.", other.kind()))),
+    }
+}
+
+/// `type(value)`, the name of the kind of value it is, eg. "number"
+fn type_of(arguments: &[Object]) -> CblResult<Object> {
+    Ok(Object::String(arguments[0].kind().to_string()))
+}
+
+/// The most decimal places "{:.N}" can ask `format` for
+pub const MAX_FORMAT_PRECISION: usize = 100;
+
+/// `format(template, args...)`, the template with each "{}" replaced
+/// by the next argument. "{:.2}" writes a number with 2 decimal places
+/// and "{{" / "}}" are literal braces
+fn format(arguments: &[Object]) -> CblResult<Object> {
+    let template = match &arguments[0] {
+        Object::String(s) => s,
+        other => return Err(native_error(&format!("format() expects a string template, got {}.", other.kind()))),
+    };
+    let mut values = arguments[1..].iter();
+
+    let mut r = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                r.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                r.push('}');
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let spec = match rest.find('}') {
+                    Some(end) => &rest[..end],
+                    None => return Err(native_error("format() placeholder is missing its '}'.")),
+                };
+                chars = rest[spec.len() + 1..].chars();
+
+                let value = values
+                    .next()
+                    .ok_or_else(|| native_error("format() has more placeholders than arguments."))?;
+                match spec {
+                    "" => r.push_str(&value.to_string()),
+                    _ => {
+                        let precision = spec
+                            .strip_prefix(":.")
+                            .and_then(|digits| digits.parse::<usize>().ok())
+                            .ok_or_else(|| native_error(&format!("format() doesn't understand '{{{}}}'.", spec)))?;
+                        if precision > MAX_FORMAT_PRECISION {
+                            return Err(native_error(&format!(
+                                "format() can't write more than {} decimal places.",
+                                MAX_FORMAT_PRECISION
+                            )));
+                        }
+                        let n = value.as_number().ok_or_else(|| {
+                            native_error(&format!("format() can only set the precision of numbers, got {}.", value.kind()))
+                        })?;
+                        r.push_str(&format!("{:.*}", precision, n));
+                    }
+                }
+            }
+            '}' => return Err(native_error("format() has a '}' without a '{', write '}}' for a brace.")),
+            c => r.push(c),
+        }
+    }
+
+    if values.next().is_some() {
+        return Err(native_error("format() has more arguments than placeholders."));
+    }
+    Ok(Object::String(r))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> f64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+// SystemTime::now panics in the browser, ask JavaScript instead
+#[cfg(target_arch = "wasm32")]
+fn now() -> f64 {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_namespace = Date, js_name = now)]
+        fn date_now() -> f64;
+    }
+
+    date_now() / 1000.0
+}
@@ -1,39 +1,639 @@
 use crate::{
-    ast::Expr,
-    token::{Object, Token, TokenType}, error::{Error, CblResult},
+    ast::{next_expr_id, Expr, Stmt},
+    token::{Object, Token, TokenType}, error::{Error, CblResult, Warning},
 };
 
+/// Most arguments a call can pass, and parameters a function can take
+pub const MAX_ARGUMENTS: usize = 255;
+
+/// How deeply expressions and statements may nest unless the parser is
+/// told otherwise, see `Parser::with_max_depth`. Each level of
+/// parentheses takes a stack frame for every precedence level, so deep
+/// nesting needs a bigger stack than a thread gets by default, see
+/// `runner::with_large_stack`
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    errors: Vec<Error>,
+    /// Problems that don't stop the program from running
+    warnings: Vec<Warning>,
+    /// How many function bodies we are inside of, `return` is only
+    /// allowed when this is above zero
+    function_depth: usize,
+    /// Whether the innermost function is a class's `init` method, which
+    /// can't return a value
+    in_initializer: bool,
+    /// How many class bodies we are inside of, for `this`
+    class_depth: usize,
+    /// Whether the innermost class has a superclass, for `super`
+    in_subclass: bool,
+    /// How many loops we are inside of, within the current function,
+    /// for `break` and `continue`
+    loop_depth: usize,
+    /// How many nested expressions / statements we are inside of
+    depth: usize,
+    max_depth: usize,
+    /// Stop at the first error instead of synchronizing and carrying on
+    strict: bool,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        // comments from a scanner with trivia turned on mean nothing here
+        let tokens = tokens
+            .into_iter()
+            .filter(|token| token.type_ != TokenType::Comment)
+            .collect();
+
+        Self {
+            tokens,
+            current: 0,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            function_depth: 0,
+            in_initializer: false,
+            class_depth: 0,
+            in_subclass: false,
+            loop_depth: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            strict: false,
+        }
     }
 
-    /// Parse the tokens into an AST.
-    pub fn parse(&mut self) -> CblResult<Expr> {
-        self.expression()
+    /// Give up with an error once nesting gets deeper than `max_depth`,
+    /// rather than overflowing the stack on input like "((((...))))"
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
     }
 
-    fn expression(&mut self) -> CblResult<Expr> {
-        self.equality()
+    /// When `strict` is set, `parse` gives up at the first error rather
+    /// than skipping to the next statement to look for more, eg. for
+    /// tooling that only wants the first problem
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
     }
 
-    fn equality(&mut self) -> CblResult<Expr> {
-        let mut expr = match self.comparison() {
-            Ok(expr) => expr,
-            Err(e) => return Err(e) 
+    /// Parse the tokens into a list of statements.
+    /// After an error the parser synchronizes to the next statement and
+    /// keeps going so that every error gets recorded, but if there were
+    /// any the first one is returned instead of the statements
+    pub fn parse(&mut self) -> CblResult<Vec<Stmt>> {
+        let mut statements = Vec::new();
+        while !self.is_at_end() {
+            match self.declaration() {
+                Ok(statement) => statements.push(statement),
+                Err(error) => {
+                    self.errors.push(error);
+                    if self.strict {
+                        break;
+                    }
+                    self.synchronize();
+                }
+            }
+        }
+
+        match self.errors.first() {
+            Some(error) => Err(error.clone()),
+            None => Ok(statements),
+        }
+    }
+
+    /// Every error encountered while parsing, in source order
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    /// Every warning found while parsing, in source order. These are
+    /// kept even when `parse` succeeds
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Every token being parsed, ending with EOF
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// Index in `tokens` of the next token to be parsed
+    pub fn position(&self) -> usize {
+        self.current
+    }
+
+    /// Type of the next token to be parsed
+    pub fn peek_type(&self) -> &TokenType {
+        &self.peek().type_
+    }
+
+    /// The token `n` places after the next one, so `peek_n(0)` is the
+    /// next token. Looking past the end gives the EOF token
+    pub fn peek_n(&self, n: usize) -> &Token {
+        let last = self.tokens.len() - 1;
+        &self.tokens[(self.current + n).min(last)]
+    }
+
+    fn declaration(&mut self) -> CblResult<Stmt> {
+        if self.match_token(&[TokenType::Class]) {
+            return self.class_declaration();
+        }
+
+        if self.match_token(&[TokenType::Fun]) {
+            return self.function("function");
+        }
+
+        if self.match_token(&[TokenType::Var]) {
+            return self.var_declaration();
+        }
+
+        if self.match_token(&[TokenType::Const]) {
+            return self.const_declaration();
+        }
+
+        self.statement()
+    }
+
+    /// "class Name < Superclass { method() { ... } ... }", methods are
+    /// written like functions without the `fun`
+    fn class_declaration(&mut self) -> CblResult<Stmt> {
+        let name = self.consume(TokenType::Identifier, "Expect class name.")?.clone();
+
+        let superclass = if self.match_token(&[TokenType::Less]) {
+            let superclass = self.consume(TokenType::Identifier, "Expect superclass name.")?.clone();
+            if superclass.lexeme == name.lexeme {
+                return Err(Error::parser_error(&superclass, "A class can't inherit from itself."));
+            }
+            Some(Expr::Variable {
+                id: next_expr_id(),
+                name: superclass,
+            })
+        } else {
+            None
+        };
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        let in_subclass = std::mem::replace(&mut self.in_subclass, superclass.is_some());
+        self.class_depth += 1;
+        let mut methods = Vec::new();
+        let result = loop {
+            if self.check(TokenType::RightBrace) || self.is_at_end() {
+                break Ok(());
+            }
+            match self.function("method") {
+                Ok(method) => methods.push(method),
+                Err(error) => break Err(error),
+            }
+        };
+        self.class_depth -= 1;
+        self.in_subclass = in_subclass;
+        result?;
+
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+        Ok(Stmt::Class {
+            name,
+            superclass,
+            methods,
+        })
+    }
+
+    /// "fun name(a, b) { ... }", `kind` is only used in error messages
+    fn function(&mut self, kind: &str) -> CblResult<Stmt> {
+        let name = self.consume(TokenType::Identifier, &format!("Expect {} name.", kind))?.clone();
+        self.consume(TokenType::LeftParen, &format!("Expect '(' after {} name.", kind))?;
+
+        let mut params = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    let message = format!("Can't have more than {} parameters.", MAX_ARGUMENTS);
+                    return Err(Error::parser_error(self.peek(), &message));
+                }
+                params.push(self.consume(TokenType::Identifier, "Expect parameter name.")?.clone());
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+
+        self.consume(TokenType::LeftBrace, &format!("Expect '{{' before {} body.", kind))?;
+        // a loop around the declaration can't be broken out of from inside
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let in_initializer = std::mem::replace(&mut self.in_initializer, kind == "method" && name.lexeme == "init");
+        self.function_depth += 1;
+        let body = self.block();
+        self.function_depth -= 1;
+        self.in_initializer = in_initializer;
+        self.loop_depth = loop_depth;
+
+        Ok(Stmt::Function {
+            name,
+            params,
+            body: body?,
+        })
+    }
+
+    fn var_declaration(&mut self) -> CblResult<Stmt> {
+        let name = self.consume(TokenType::Identifier, "Expect variable name.")?.clone();
+
+        let initializer = if self.match_token(&[TokenType::Equal]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(TokenType::Semicolon, "Expect ';' after variable declaration.")?;
+        Ok(Stmt::Var { name, initializer })
+    }
+
+    fn const_declaration(&mut self) -> CblResult<Stmt> {
+        let name = self.consume(TokenType::Identifier, "Expect constant name.")?.clone();
+        self.consume(TokenType::Equal, "Expect '=' after constant name.")?;
+        let initializer = self.expression()?;
+
+        self.consume(TokenType::Semicolon, "Expect ';' after constant declaration.")?;
+        Ok(Stmt::Const { name, initializer })
+    }
+
+    fn statement(&mut self) -> CblResult<Stmt> {
+        self.nested(Self::statement_inner)
+    }
+
+    fn statement_inner(&mut self) -> CblResult<Stmt> {
+        if self.match_token(&[TokenType::Assert]) {
+            return self.assert_statement();
+        }
+
+        if self.match_token(&[TokenType::Break, TokenType::Continue]) {
+            return self.loop_jump();
+        }
+
+        if self.match_token(&[TokenType::For]) {
+            return self.for_statement();
+        }
+
+        if self.match_token(&[TokenType::If]) {
+            return self.if_statement();
+        }
+
+        if self.match_token(&[TokenType::Print]) {
+            return self.print_statement();
+        }
+
+        if self.match_token(&[TokenType::Return]) {
+            return self.return_statement();
+        }
+
+        if self.match_token(&[TokenType::While]) {
+            return self.while_statement();
+        }
+
+        if self.match_token(&[TokenType::LeftBrace]) {
+            return Ok(Stmt::Block {
+                line: self.previous().line,
+                statements: self.block()?,
+            });
+        }
+
+        self.expression_statement()
+    }
+
+    /// Collect declarations until the closing '}' of the block. Anything
+    /// after a `return` can't run, which is warned about once per block
+    fn block(&mut self) -> CblResult<Vec<Stmt>> {
+        let mut statements = Vec::new();
+        let mut warned = false;
+
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            if !warned && matches!(statements.last(), Some(Stmt::Return { .. })) {
+                self.warnings.push(Warning::at(self.peek(), "Unreachable statement after 'return'."));
+                warned = true;
+            }
+            statements.push(self.declaration()?);
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
+    /// `break;` or `continue;`, only allowed inside of a loop
+    fn loop_jump(&mut self) -> CblResult<Stmt> {
+        let keyword = self.previous().clone();
+        if self.loop_depth == 0 {
+            let message = format!("Can't use '{}' outside of a loop.", keyword.lexeme);
+            return Err(Error::parser_error(&keyword, &message));
+        }
+
+        self.consume(TokenType::Semicolon, &format!("Expect ';' after '{}'.", keyword.lexeme))?;
+        Ok(match keyword.type_ {
+            TokenType::Break => Stmt::Break { keyword },
+            _ => Stmt::Continue { keyword },
+        })
+    }
+
+    /// Parse a loop body, in which `break` and `continue` are allowed
+    fn loop_body(&mut self) -> CblResult<Stmt> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    /// There is no for loop in the AST, instead
+    /// "for (init; condition; increment) body" is desugared into
+    /// "{ init; while (condition) body }" with the increment attached to
+    /// the while loop so that it runs after every iteration
+    fn for_statement(&mut self) -> CblResult<Stmt> {
+        let line = self.previous().line;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+
+        // "for (x in items)", with an optional `var` before the name
+        let var = usize::from(self.check(TokenType::Var));
+        if self.peek_n(var).type_ == TokenType::Identifier && self.peek_n(var + 1).type_ == TokenType::In {
+            return self.for_each_statement();
+        }
+
+        let initializer = if self.match_token(&[TokenType::Semicolon]) {
+            None
+        } else if self.match_token(&[TokenType::Var]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check(TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition.")?;
+
+        let increment = if self.check(TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        let body = self.loop_body()?;
+
+        // a missing condition loops forever
+        let condition = condition.unwrap_or(Expr::Literal {
+            value: Object::Bool(true),
+        });
+        let mut body = Stmt::While {
+            line,
+            condition,
+            body: Box::new(body),
+            increment,
+        };
+
+        if let Some(initializer) = initializer {
+            body = Stmt::Block {
+                line,
+                statements: vec![initializer, body],
+            };
+        }
+
+        Ok(body)
+    }
+
+    /// "for (x in items) body", after the '('
+    fn for_each_statement(&mut self) -> CblResult<Stmt> {
+        self.match_token(&[TokenType::Var]);
+        let name = self.consume(TokenType::Identifier, "Expect loop variable name.")?.clone();
+        self.consume(TokenType::In, "Expect 'in' after loop variable.")?;
+        let iterable = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        let body = self.loop_body()?;
+        Ok(Stmt::ForEach {
+            name,
+            iterable,
+            body: Box::new(body),
+        })
+    }
+
+    /// The else is bound to the nearest if, so "if (a) if (b) x; else y;"
+    /// attaches the else to "if (b)"
+    fn if_statement(&mut self) -> CblResult<Stmt> {
+        let line = self.previous().line;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.match_token(&[TokenType::Else]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::If {
+            line,
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    /// "assert condition;" or "assert condition, message;"
+    fn assert_statement(&mut self) -> CblResult<Stmt> {
+        let keyword = self.previous().clone();
+        let condition = self.expression()?;
+        let message = if self.match_token(&[TokenType::Comma]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after assertion.")?;
+        Ok(Stmt::Assert {
+            keyword,
+            condition,
+            message,
+        })
+    }
+
+    fn print_statement(&mut self) -> CblResult<Stmt> {
+        let line = self.previous().line;
+        let expression = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        Ok(Stmt::Print { line, expression })
+    }
+
+    fn return_statement(&mut self) -> CblResult<Stmt> {
+        let keyword = self.previous().clone();
+        if self.function_depth == 0 {
+            return Err(Error::parser_error(&keyword, "Can't return from top-level code."));
+        }
+
+        let value = if self.check(TokenType::Semicolon) {
+            None
+        } else if self.in_initializer {
+            return Err(Error::parser_error(&keyword, "Can't return a value from an initializer."));
+        } else {
+            Some(self.expression()?)
         };
 
-        while self.match_token(vec![TokenType::BangEqual, TokenType::EqualEqual]) {
-            let operator = self.previous();
-            let right = match self.comparison() {
-                Ok(expr) => expr,
-                Err(e) => return Err(e),
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
+        Ok(Stmt::Return { keyword, value })
+    }
+
+    fn while_statement(&mut self) -> CblResult<Stmt> {
+        let line = self.previous().line;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
+        let body = Box::new(self.loop_body()?);
+
+        Ok(Stmt::While {
+            line,
+            condition,
+            body,
+            increment: None,
+        })
+    }
+
+    fn expression_statement(&mut self) -> CblResult<Stmt> {
+        let line = self.peek().line;
+        let expression = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
+        Ok(Stmt::Expression { line, expression })
+    }
+
+    fn expression(&mut self) -> CblResult<Expr> {
+        self.nested(Self::assignment)
+    }
+
+    /// Assignment is right associative, so "a = b = 1" assigns 1 to both
+    /// a and b. A compound assignment like "a += b" is kept as its own
+    /// expression, so that in "a[i] += b" a and i are only evaluated once
+    fn assignment(&mut self) -> CblResult<Expr> {
+        let expr = self.nil_coalesce()?;
+
+        if self.match_token(&[
+            TokenType::Equal,
+            TokenType::PlusEqual,
+            TokenType::MinusEqual,
+            TokenType::StarEqual,
+            TokenType::SlashEqual,
+        ]) {
+            let equals = self.previous().clone();
+            let value = self.nested(Self::assignment)?;
+
+            let operator = match equals.type_ {
+                TokenType::PlusEqual => Some((TokenType::Plus, "+")),
+                TokenType::MinusEqual => Some((TokenType::Minus, "-")),
+                TokenType::StarEqual => Some((TokenType::Star, "*")),
+                TokenType::SlashEqual => Some((TokenType::Slash, "/")),
+                _ => None,
+            };
+            if let Some((type_, lexeme)) = operator {
+                if !matches!(expr, Expr::Variable { .. } | Expr::Index { .. } | Expr::Get { .. }) {
+                    return Err(Error::parser_error(&equals, "Invalid assignment target."));
+                }
+
+                let mut operator = equals;
+                operator.type_ = type_;
+                operator.lexeme = lexeme.to_string();
+                return Ok(Expr::CompoundAssign {
+                    target: Box::new(expr),
+                    operator,
+                    value: Box::new(value),
+                });
+            }
+
+            match expr {
+                Expr::Variable { name, .. } => {
+                    return Ok(Expr::Assign {
+                        id: next_expr_id(),
+                        name,
+                        value: Box::new(value),
+                    });
+                }
+                Expr::Index { object, bracket, index } => {
+                    return Ok(Expr::IndexAssign {
+                        object,
+                        bracket,
+                        index,
+                        value: Box::new(value),
+                    });
+                }
+                Expr::Get { object, name } => {
+                    return Ok(Expr::Set {
+                        object,
+                        name,
+                        value: Box::new(value),
+                    });
+                }
+                _ => {}
+            }
+
+            return Err(Error::parser_error(&equals, "Invalid assignment target."));
+        }
+
+        Ok(expr)
+    }
+
+    /// "a ?? b" gives a unless it is nil, binding more loosely than "or"
+    fn nil_coalesce(&mut self) -> CblResult<Expr> {
+        let mut expr = self.logical_or()?;
+
+        while self.match_token(&[TokenType::QuestionQuestion]) {
+            let operator = self.previous().clone();
+            let right = self.logical_or()?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
             };
+        }
+
+        Ok(expr)
+    }
+
+    fn logical_or(&mut self) -> CblResult<Expr> {
+        let mut expr = self.logical_and()?;
+
+        while self.match_token(&[TokenType::Or]) {
+            let operator = self.previous().clone();
+            let right = self.logical_and()?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn logical_and(&mut self) -> CblResult<Expr> {
+        let mut expr = self.equality()?;
+
+        while self.match_token(&[TokenType::And]) {
+            let operator = self.previous().clone();
+            let right = self.equality()?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> CblResult<Expr> {
+        let mut expr = self.comparison()?;
+
+        while self.match_token(&[TokenType::BangEqual, TokenType::EqualEqual]) {
+            let operator = self.previous().clone();
+            let right = self.comparison()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -44,15 +644,26 @@ impl Parser {
         Ok(expr)
     }
 
-    fn match_token(&mut self, types: Vec<TokenType>) -> bool {
-        for token in types {
-            if self.check(token) {
-                self.advance();
-                return true;
-            }
+    /// Run one of the recursive rules, keeping track of how deep we are
+    fn nested<T>(&mut self, rule: fn(&mut Self) -> CblResult<T>) -> CblResult<T> {
+        if self.depth >= self.max_depth {
+            return Err(Error::parser_error(self.peek(), "Too much nesting."));
         }
 
-        false
+        self.depth += 1;
+        let result = rule(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Consume the next token if it is any of `types`
+    fn match_token(&mut self, types: &[TokenType]) -> bool {
+        if self.is_at_end() || !types.contains(&self.peek().type_) {
+            return false;
+        }
+
+        self.advance();
+        true
     }
 
     fn check(&self, type_: TokenType) -> bool {
@@ -63,7 +674,7 @@ impl Parser {
         self.peek().type_ == type_
     }
 
-    fn advance(&mut self) -> Token {
+    fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
             self.current += 1;
         }
@@ -75,31 +686,71 @@ impl Parser {
         self.peek().type_ == TokenType::Eof
     }
 
-    fn peek(&self) -> Token {
-        self.tokens[self.current].clone()
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
     }
 
-    fn previous(&self) -> Token {
-        self.tokens[self.current - 1].clone()
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current - 1]
     }
 
+    /// Comparisons don't chain, "1 < 2 < 3" would compare a bool with
+    /// 3 which is almost certainly a mistake, so it is rejected here
     fn comparison(&mut self) -> CblResult<Expr> {
-        let mut expr = match self.term() {
-            Ok(expr) => expr,
-            Err(e) => return Err(e),
-        };
-
-        while self.match_token(vec![
+        let comparisons = [
             TokenType::Greater,
             TokenType::GreaterEqual,
             TokenType::Less,
             TokenType::LessEqual,
-        ]) {
-            let operator = self.previous();
-            let right = match self.term() {
-                Ok(expr) => expr,
-                Err(e) => return Err(e),
+        ];
+        let expr = self.bitwise_or()?;
+
+        if !self.match_token(&comparisons) {
+            return Ok(expr);
+        }
+
+        let operator = self.previous().clone();
+        let right = self.bitwise_or()?;
+
+        if self.match_token(&comparisons) {
+            return Err(Error::parser_error(
+                self.previous(),
+                "Chained comparison operators are not allowed; use explicit parentheses.",
+            ));
+        }
+
+        Ok(Expr::Binary {
+            left: Box::new(expr),
+            operator,
+            right: Box::new(right),
+        })
+    }
+
+    /// The bitwise operators bind tighter than comparisons, so
+    /// "a & 1 == 0" checks whether a is even rather than comparing with a
+    /// bool like it would in C
+    fn bitwise_or(&mut self) -> CblResult<Expr> {
+        let mut expr = self.bitwise_xor()?;
+
+        while self.match_token(&[TokenType::Pipe]) {
+            let operator = self.previous().clone();
+            let right = self.bitwise_xor()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
             };
+        }
+
+        Ok(expr)
+    }
+
+    fn bitwise_xor(&mut self) -> CblResult<Expr> {
+        let mut expr = self.bitwise_and()?;
+
+        while self.match_token(&[TokenType::Caret]) {
+            let operator = self.previous().clone();
+            let right = self.bitwise_and()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -110,18 +761,44 @@ impl Parser {
         Ok(expr)
     }
 
-    fn term(&mut self) -> CblResult<Expr> {
-        let mut expr = match self.factor() {
-            Ok(expr) => expr,
-            Err(e) => return Err(e),
-        };
+    fn bitwise_and(&mut self) -> CblResult<Expr> {
+        let mut expr = self.shift()?;
+
+        while self.match_token(&[TokenType::Ampersand]) {
+            let operator = self.previous().clone();
+            let right = self.shift()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
 
-        while self.match_token(vec![TokenType::Minus, TokenType::Plus]) {
-            let operator = self.previous();
-            let right = match self.factor() {
-                Ok(expr) => expr,
-                Err(e) => return Err(e),
+    fn shift(&mut self) -> CblResult<Expr> {
+        let mut expr = self.term()?;
+
+        while self.match_token(&[TokenType::LessLess, TokenType::GreaterGreater]) {
+            let operator = self.previous().clone();
+            let right = self.term()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
             };
+        }
+
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> CblResult<Expr> {
+        let mut expr = self.factor()?;
+
+        while self.match_token(&[TokenType::Minus, TokenType::Plus]) {
+            let operator = self.previous().clone();
+            let right = self.factor()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -133,18 +810,12 @@ impl Parser {
     }
 
     fn factor(&mut self) -> CblResult<Expr> {
-        let mut expr = match self.unary() {
-            Ok(expr) => expr,
-            Err(e) => return Err(e),
-        };
+        let mut expr = self.unary()?;
 
 
-        while self.match_token(vec![TokenType::Slash, TokenType::Star]) {
-            let operator = self.previous();
-            let right = match self.unary() {
-                Ok(expr) => expr,
-                Err(e) => return Err(e),
-            };
+        while self.match_token(&[TokenType::Slash, TokenType::Star, TokenType::Percent]) {
+            let operator = self.previous().clone();
+            let right = self.unary()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -156,12 +827,9 @@ impl Parser {
     }
 
     fn unary(&mut self) -> CblResult<Expr> {
-        if self.match_token(vec![TokenType::Bang, TokenType::Minus]) {
-            let operator = self.previous();
-            let right = match self.unary() {
-                Ok(expr) => expr,
-                Err(e) => return Err(e),
-            };
+        if self.match_token(&[TokenType::Bang, TokenType::Minus, TokenType::Plus]) {
+            let operator = self.previous().clone();
+            let right = self.nested(Self::unary)?;
 
             return Ok(Expr::Unary {
                 operator,
@@ -169,55 +837,274 @@ impl Parser {
             });
         }
 
-        self.primary()
+        self.exponent()
+    }
+
+    /// "**" binds tighter than unary operators and groups right to left,
+    /// so "-2 ** 2" is -4 and "2 ** 3 ** 2" is 2 ** 9
+    fn exponent(&mut self) -> CblResult<Expr> {
+        let expr = self.call()?;
+
+        if self.match_token(&[TokenType::StarStar]) {
+            let operator = self.previous().clone();
+            let right = self.nested(Self::unary)?;
+            return Ok(Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            });
+        }
+
+        Ok(expr)
+    }
+
+    /// A primary expression followed by any number of argument lists or
+    /// indexes, eg. "clock()", "f(1)(2)" or "a[0][1]", and optionally
+    /// "++" or "--"
+    fn call(&mut self) -> CblResult<Expr> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.match_token(&[TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_token(&[TokenType::Dot]) {
+                let name = self.consume(TokenType::Identifier, "Expect property name after '.'.")?.clone();
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name,
+                };
+            } else if self.match_token(&[TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                let bracket = self.consume(TokenType::RightBracket, "Expect ']' after index.")?.clone();
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
+                };
+            } else {
+                break;
+            }
+        }
+
+        while self.match_token(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let operator = self.previous().clone();
+            if !matches!(expr, Expr::Variable { .. } | Expr::Index { .. } | Expr::Get { .. }) {
+                return Err(Error::parser_error(&operator, "Invalid increment target."));
+            }
+            expr = Expr::Postfix {
+                target: Box::new(expr),
+                operator,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    /// The arguments of a call, after its '('. A trailing comma like
+    /// "f(1, 2,)" isn't allowed, the same as in a parameter list
+    fn finish_call(&mut self, callee: Expr) -> CblResult<Expr> {
+        let mut arguments = Vec::new();
+
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    let message = format!("Can't have more than {} arguments.", MAX_ARGUMENTS);
+                    return Err(Error::parser_error(self.peek(), &message));
+                }
+                arguments.push(self.expression()?);
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?.clone();
+
+        Ok(Expr::Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        })
+    }
+
+    /// "super.method", the 'super' has already been consumed. Kept out
+    /// of `primary` so it doesn't grow the stack frame of every nested
+    /// expression
+    fn super_expr(&mut self) -> CblResult<Expr> {
+        let keyword = self.previous().clone();
+        if self.class_depth == 0 {
+            return Err(Error::parser_error(&keyword, "Can't use 'super' outside of a class."));
+        }
+        if !self.in_subclass {
+            return Err(Error::parser_error(&keyword, "Can't use 'super' in a class with no superclass."));
+        }
+        self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+        let method = self.consume(TokenType::Identifier, "Expect superclass method name.")?.lexeme.clone();
+        Ok(Expr::Super {
+            id: next_expr_id(),
+            keyword,
+            method,
+        })
     }
 
     fn primary(&mut self) -> CblResult<Expr> {
-        if self.match_token(vec![TokenType::False]) {
+        if self.match_token(&[TokenType::False]) {
             return Ok(Expr::Literal {
                 value: Object::Bool(false),
             });
         }
 
-        if self.match_token(vec![TokenType::True]) {
+        if self.match_token(&[TokenType::True]) {
             return Ok(Expr::Literal {
                 value: Object::Bool(true),
             });
         }
 
-        if self.match_token(vec![TokenType::Nil]) {
+        if self.match_token(&[TokenType::Nil]) {
             return Ok(Expr::Literal { value: Object::Nil });
         }
 
-        if self.match_token(vec![TokenType::Number, TokenType::String]) {
+        if self.match_token(&[TokenType::Number]) {
             return Ok(Expr::Literal {
-                value: self.previous().literal,
+                value: self.previous().literal.clone(),
             });
         }
 
-        if self.match_token(vec![TokenType::LeftParen]) {
-            let expr = match self.expression() {
-                Ok(expr) => expr,
-                Err(e) => return Err(e),
-            };
-            match self.consume(TokenType::RightParen, "Expect ')' after expression.") {
-                Ok(_) => {}
-                Err(e) => return Err(e),
-            };
+        // adjacent string literals are joined like in C, so a long
+        // message can be split over several lines: "ab" "cd" is "abcd"
+        if self.match_token(&[TokenType::String]) {
+            let mut value = self.previous().literal.to_string();
+            while self.match_token(&[TokenType::String]) {
+                value.push_str(&self.previous().literal.to_string());
+            }
+            return Ok(Expr::Literal {
+                value: Object::String(value),
+            });
+        }
+
+        if self.match_token(&[TokenType::Interpolation]) {
+            return self.interpolation();
+        }
+
+        if self.match_token(&[TokenType::Super]) {
+            return self.super_expr();
+        }
+
+        if self.match_token(&[TokenType::This]) {
+            let keyword = self.previous().clone();
+            if self.class_depth == 0 {
+                return Err(Error::parser_error(&keyword, "Can't use 'this' outside of a class."));
+            }
+            return Ok(Expr::This {
+                id: next_expr_id(),
+                keyword,
+            });
+        }
+
+        if self.match_token(&[TokenType::Identifier]) {
+            return Ok(Expr::Variable {
+                id: next_expr_id(),
+                name: self.previous().clone(),
+            });
+        }
+
+        // `print` is a statement, but inside an expression it refers to
+        // the `print()` native, eg. "var x = print(y);"
+        if self.match_token(&[TokenType::Print]) {
+            // the keyword may have been renamed, the native never is
+            let mut name = self.previous().clone();
+            name.type_ = TokenType::Identifier;
+            name.lexeme = "print".to_string();
+            return Ok(Expr::Variable {
+                id: next_expr_id(),
+                name,
+            });
+        }
+
+        if self.match_token(&[TokenType::LeftParen]) {
+            let expr = self.expression()?;
+            self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
             return Ok(Expr::Grouping {
                 expression: Box::new(expr),
             });
         }
 
-        Err(Error::parser_error("Expect expression."))
+        if self.match_token(&[TokenType::LeftBracket]) {
+            let mut elements = Vec::new();
+            if !self.check(TokenType::RightBracket) {
+                loop {
+                    elements.push(self.expression()?);
+                    if !self.match_token(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBracket, "Expect ']' after array elements.")?;
+            return Ok(Expr::Array { elements });
+        }
+
+        if self.match_token(&[TokenType::LeftBrace]) {
+            let brace = self.previous().clone();
+            let mut entries = Vec::new();
+            if !self.check(TokenType::RightBrace) {
+                loop {
+                    let key = self.expression()?;
+                    self.consume(TokenType::Colon, "Expect ':' after map key.")?;
+                    entries.push((key, self.expression()?));
+                    if !self.match_token(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBrace, "Expect '}' after map entries.")?;
+            return Ok(Expr::Map { brace, entries });
+        }
+
+        Err(Error::parser_error(self.peek(), "Expect expression."))
     }
 
-    fn consume(&mut self, type_: TokenType, message: &str) -> CblResult<Token> {
+    /// "a ${b} c" is scanned as Interpolation("a "), b, String(" c") and
+    /// is turned into "a " + b + " c". Starting with a string means
+    /// every part is converted to a string as it is added
+    fn interpolation(&mut self) -> CblResult<Expr> {
+        let mut expr = Expr::Literal {
+            value: self.previous().literal.clone(),
+        };
+
+        loop {
+            let plus = Token::new(TokenType::Plus, "+".to_string(), Object::Nil, self.previous().line, self.previous().column);
+            let embedded = self.expression()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator: plus.clone(),
+                right: Box::new(embedded),
+            };
+
+            let done = !self.match_token(&[TokenType::Interpolation]);
+            if done {
+                self.consume(TokenType::String, "Expect '}' after interpolated expression.")?;
+            }
+
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator: plus,
+                right: Box::new(Expr::Literal {
+                    value: self.previous().literal.clone(),
+                }),
+            };
+
+            if done {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn consume(&mut self, type_: TokenType, message: &str) -> CblResult<&Token> {
         if self.check(type_) {
             return Ok(self.advance());
         }
 
-        Err(Error::parser_error(message))
+        Err(Error::parser_error(self.peek(), message))
     }
 
     /// Discard tokens until we reach a statement boundary.
@@ -234,10 +1121,14 @@ impl Parser {
                 TokenType::Class
                 | TokenType::Fun
                 | TokenType::Var
+                | TokenType::Const
                 | TokenType::For
                 | TokenType::If
                 | TokenType::While
+                | TokenType::Break
+                | TokenType::Continue
                 | TokenType::Print
+                | TokenType::Assert
                 | TokenType::Return => return,
                 _ => {}
             }
@@ -252,16 +1143,516 @@ mod tests {
     use super::*;
     use crate::scanner::Scanner;
     use crate::ast::AstPrinter;
+    use crate::runner::with_large_stack;
 
     #[test]
     fn test_parser() {
-        let mut scanner = Scanner::new("-123 * 45.67");
-        let tokens = scanner.scan_tokens();
+        let mut scanner = Scanner::new("-123 * 45.67;");
+        let tokens = scanner.scan_tokens().unwrap();
 
         let mut parser = Parser::new(tokens);
-        let expression = parser.parse().expect("Could not parse sample code.");
-        let printer = AstPrinter;
+        let mut statements = parser.parse().expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+
+        let expected = Expr::Binary {
+            left: Box::new(Expr::Unary {
+                operator: Token::new(TokenType::Minus, "-".to_string(), Object::Nil, 1, 1).with_span(0..1),
+                right: Box::new(Expr::Literal {
+                    value: Object::Int(123),
+                }),
+            }),
+            operator: Token::new(TokenType::Star, "*".to_string(), Object::Nil, 1, 6).with_span(5..6),
+            right: Box::new(Expr::Literal {
+                value: Object::Number(45.67),
+            }),
+        };
+        assert_eq!(statements, vec![Stmt::Expression { line: 1, expression: expected }]);
 
+        let expression = match statements.remove(0) {
+            Stmt::Expression { expression, .. } => expression,
+            _ => panic!("Expected an expression statement."),
+        };
         assert_eq!(printer.print(expression).unwrap(), "(* (- 123) 45.67)");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_assignment() {
+        let mut scanner = Scanner::new("a = b = 1;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let mut statements = parser.parse().expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+
+        let expression = match statements.remove(0) {
+            Stmt::Expression { expression, .. } => expression,
+            _ => panic!("Expected an expression statement."),
+        };
+        assert_eq!(printer.print(expression).unwrap(), "(= a (= b 1))");
+    }
+
+    #[test]
+    fn test_variable() {
+        let tokens = Scanner::new("foo + 1;").scan_tokens().unwrap();
+        let mut statements = Parser::new(tokens).parse().unwrap();
+
+        match statements.remove(0) {
+            Stmt::Expression {
+                expression: Expr::Binary { left, .. },
+                ..
+            } => match *left {
+                Expr::Variable { name, .. } => assert_eq!(name.lexeme, "foo"),
+                other => panic!("Expected a variable, got {:?}", other),
+            },
+            _ => panic!("Expected a binary expression statement."),
+        }
+    }
+
+    #[test]
+    fn test_invalid_assignment_target() {
+        let mut scanner = Scanner::new("1 = 2;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        match parser.parse() {
+            Err(Error::ParserError { message, line, .. }) => {
+                assert_eq!(message, "Invalid assignment target.");
+                assert_eq!(line, 1);
+            }
+            _ => panic!("Expected a parser error."),
+        }
+    }
+
+    #[test]
+    fn test_const_needs_initializer() {
+        let tokens = Scanner::new("const a;").scan_tokens().unwrap();
+        match Parser::new(tokens).parse() {
+            Err(Error::ParserError { message, .. }) => assert_eq!(message, "Expect '=' after constant name."),
+            _ => panic!("Expected a parser error."),
+        }
+
+        let tokens = Scanner::new("const a = 1;").scan_tokens().unwrap();
+        assert_eq!(AstPrinter.print_stmts(&Parser::new(tokens).parse().unwrap()).unwrap(), "(const a 1)");
+    }
+
+    #[test]
+    fn test_unterminated_block() {
+        let mut scanner = Scanner::new("{ var a = 1;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_for_desugars_to_while() {
+        let mut scanner = Scanner::new("for (;;) print 1;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().expect("Could not parse sample code.");
+
+        assert_eq!(statements.len(), 1);
+        assert!(matches!(statements[0], Stmt::While { .. }));
+    }
+
+    #[test]
+    fn test_for_desugars_all_clauses() {
+        let mut scanner = Scanner::new("for (var i = 0; i < 3; i = i + 1) print i;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+
+        assert_eq!(
+            printer.print_stmts(&statements).unwrap(),
+            "(block (var i 0) (while (< i 3) (print i) (= i (+ i 1))))"
+        );
+    }
+
+    #[test]
+    fn test_nil_coalesce_precedence() {
+        let tokens = Scanner::new("a ?? b or c ?? d;").scan_tokens().unwrap();
+        let mut statements = Parser::new(tokens).parse().expect("Could not parse sample code.");
+
+        let expression = match statements.remove(0) {
+            Stmt::Expression { expression, .. } => expression,
+            _ => panic!("Expected an expression statement."),
+        };
+        assert_eq!(AstPrinter.print(expression).unwrap(), "(?? (?? a (or b c)) d)");
+    }
+
+    #[test]
+    fn test_logical_precedence() {
+        let mut scanner = Scanner::new("a or b and c;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let mut statements = parser.parse().expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+
+        let expression = match statements.remove(0) {
+            Stmt::Expression { expression, .. } => expression,
+            _ => panic!("Expected an expression statement."),
+        };
+        assert_eq!(printer.print(expression).unwrap(), "(or a (and b c))");
+    }
+
+    #[test]
+    fn test_parse_error_is_reported() {
+        let mut scanner = Scanner::new("print ;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        assert!(parser.parse().is_err());
+        assert_eq!(parser.errors().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_collects_multiple_errors() {
+        let mut scanner = Scanner::new("1 +; print 1; 2 +;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        assert!(parser.parse().is_err());
+        assert_eq!(parser.errors().len(), 2);
+    }
+
+    #[test]
+    fn test_exponent_associativity() {
+        let mut scanner = Scanner::new("2 ** 3 ** 2; -2 ** 2;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+
+        assert_eq!(
+            printer.print_stmts(&statements).unwrap(),
+            "(; (** 2 (** 3 2)))\n(; (- (** 2 2)))"
+        );
+    }
+
+    #[test]
+    fn test_call() {
+        let mut scanner = Scanner::new("clock(); f(1, a + 2)(3);");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+
+        assert_eq!(
+            printer.print_stmts(&statements).unwrap(),
+            "(; (call clock))\n(; (call (call f 1 (+ a 2)) 3))"
+        );
+    }
+
+    #[test]
+    fn test_class() {
+        let tokens = Scanner::new("class A { get() { return this.x; } } a.x = 1;").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            AstPrinter.print_stmts(&statements).unwrap(),
+            "(class A (fun get () (return (. this x))))\n(; (= (. a x) 1))"
+        );
+
+        let error = |source: &str| match Parser::new(Scanner::new(source).scan_tokens().unwrap()).parse() {
+            Err(Error::ParserError { message, .. }) => message,
+            _ => panic!("Expected a parser error."),
+        };
+        assert_eq!(error("print this;"), "Can't use 'this' outside of a class.");
+        assert_eq!(error("fun f() { return this; }"), "Can't use 'this' outside of a class.");
+        assert_eq!(error("class A { init() { return 1; } }"), "Can't return a value from an initializer.");
+        assert!(Parser::new(Scanner::new("class A { init() { return; } }").scan_tokens().unwrap()).parse().is_ok());
+    }
+
+    #[test]
+    fn test_subclass() {
+        let tokens = Scanner::new("class B < A { f() { return super.f(); } }").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            AstPrinter.print_stmts(&statements).unwrap(),
+            "(class B < A (fun f () (return (call (. super f)))))"
+        );
+
+        let error = |source: &str| match Parser::new(Scanner::new(source).scan_tokens().unwrap()).parse() {
+            Err(Error::ParserError { message, .. }) => message,
+            _ => panic!("Expected a parser error."),
+        };
+        assert_eq!(error("class A < A {}"), "A class can't inherit from itself.");
+        assert_eq!(error("super.f();"), "Can't use 'super' outside of a class.");
+        assert_eq!(error("class A { f() { super.f(); } }"), "Can't use 'super' in a class with no superclass.");
+        assert_eq!(error("class B < A { f() { super; } }"), "Expect '.' after 'super'.");
+    }
+
+    #[test]
+    fn test_get() {
+        let tokens = Scanner::new("\"Hi\".lower(); s.length + 1;").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            AstPrinter.print_stmts(&statements).unwrap(),
+            "(; (call (. Hi lower)))\n(; (+ (. s length) 1))"
+        );
+
+        let tokens = Scanner::new("s.1;").scan_tokens().unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_unreachable_warning() {
+        let source = "fun f() {\n  return 1;\n  print 2;\n  print 3;\n}\nfun g() { if (true) return; print 4; }";
+        let mut parser = Parser::new(Scanner::new(source).scan_tokens().unwrap());
+        parser.parse().unwrap();
+        assert_eq!(parser.warnings(), [Warning {
+            message: "Unreachable statement after 'return'.".to_string(),
+            line: 3,
+            column: 3,
+        }]);
+    }
+
+    #[test]
+    fn test_parse_skips_comments() {
+        let tokens = Scanner::new("print /* one */ 1; // done").with_trivia(true).scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        assert_eq!(AstPrinter.print_stmts(&statements).unwrap(), "(print 1)");
+    }
+
+    #[test]
+    fn test_for_each() {
+        let tokens = Scanner::new("for (x in [1, 2]) print x; for (var c in s) break;").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            AstPrinter.print_stmts(&statements).unwrap(),
+            "(for x in (array 1 2) (print x))\n(for c in s (break))"
+        );
+
+        // the C-style loop still parses
+        let tokens = Scanner::new("for (x = 0; x < 1; x++) {}").scan_tokens().unwrap();
+        assert!(Parser::new(tokens).parse().is_ok());
+    }
+
+    #[test]
+    fn test_lookahead() {
+        let mut parser = Parser::new(Scanner::new("var a = 1;").scan_tokens().unwrap());
+        assert_eq!(parser.position(), 0);
+        assert_eq!(parser.peek_type(), &TokenType::Var);
+        assert_eq!(parser.peek_n(1).lexeme, "a");
+        assert_eq!(parser.peek_n(100).type_, TokenType::Eof);
+        assert_eq!(parser.tokens().len(), 6);
+
+        parser.parse().unwrap();
+        assert_eq!(parser.position(), 5);
+        assert_eq!(parser.peek_type(), &TokenType::Eof);
+    }
+
+    #[test]
+    fn test_call_limits() {
+        let parse = |source: &str| Parser::new(Scanner::new(source).scan_tokens().unwrap()).parse();
+
+        assert!(parse(&format!("f({});", vec!["1"; 255].join(", "))).is_ok());
+        match parse(&format!("f({});", vec!["1"; 256].join(", "))) {
+            Err(Error::ParserError { message, .. }) => assert_eq!(message, "Can't have more than 255 arguments."),
+            _ => panic!("Expected a parser error."),
+        }
+
+        let params = (0..256).map(|i| format!("p{}", i)).collect::<Vec<String>>();
+        match parse(&format!("fun f({}) {{}}", params.join(", "))) {
+            Err(Error::ParserError { message, .. }) => assert_eq!(message, "Can't have more than 255 parameters."),
+            _ => panic!("Expected a parser error."),
+        }
+
+        assert!(parse("f(1, 2,);").is_err());
+    }
+
+    #[test]
+    fn test_function_declaration() {
+        let mut scanner = Scanner::new("fun add(a, b) { return a + b; } fun nothing() { return; }");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+
+        assert_eq!(
+            printer.print_stmts(&statements).unwrap(),
+            "(fun add (a b) (return (+ a b)))\n(fun nothing () (return))"
+        );
+    }
+
+    #[test]
+    fn test_top_level_return() {
+        let mut scanner = Scanner::new("return 1;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_adjacent_strings() {
+        let mut scanner = Scanner::new("\"ab\" \"cd\";\n\"one\"\n  \" two\" + \"!\";");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().expect("Could not parse sample code.");
+
+        assert_eq!(
+            statements[0],
+            Stmt::Expression {
+                line: 1,
+                expression: Expr::Literal {
+                    value: Object::String("abcd".to_string())
+                }
+            }
+        );
+        let mut printer = AstPrinter;
+        assert_eq!(printer.print_stmts(&statements[1..]).unwrap(), "(; (+ one two !))");
+    }
+
+    #[test]
+    fn test_chained_comparison() {
+        let mut scanner = Scanner::new("1 < 2 < 3;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        match parser.parse() {
+            Err(Error::ParserError { message, .. }) => assert_eq!(
+                message,
+                "Chained comparison operators are not allowed; use explicit parentheses."
+            ),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        let mut scanner = Scanner::new("(1 < 2) == (2 >= 3);");
+        let tokens = scanner.scan_tokens().unwrap();
+        assert!(Parser::new(tokens).parse().is_ok());
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let mut scanner = Scanner::new("var a = 1;\nprint\n;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        match parser.parse() {
+            Err(error @ Error::ParserError { .. }) => {
+                assert_eq!(error.to_string(), "[line 3] Parse error at ';': Expect expression.");
+                assert!(matches!(error, Error::ParserError { line: 3, column: 1, .. }));
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_deep_nesting_is_an_error() {
+        with_large_stack(deep_nesting_is_an_error);
+
+        let tokens = Scanner::new("((((1))));").scan_tokens().unwrap();
+        assert!(Parser::new(tokens.clone()).parse().is_ok());
+        assert!(Parser::new(tokens).with_max_depth(3).parse().is_err());
+    }
+
+    fn deep_nesting_is_an_error() {
+        for source in [
+            format!("{}1{};", "(".repeat(100_000), ")".repeat(100_000)),
+            format!("{}1;", "- ".repeat(100_000)),
+            format!("{}1;", "2 ** ".repeat(100_000)),
+            format!("{}1;", "a = ".repeat(100_000)),
+            format!("{}{}", "{".repeat(100_000), "}".repeat(100_000)),
+        ] {
+            let tokens = Scanner::new(&source).scan_tokens().unwrap();
+            match Parser::new(tokens).parse() {
+                Err(Error::ParserError { message, .. }) => assert_eq!(message, "Too much nesting."),
+                other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+            }
+        }
+
+        // the statement and the expression it holds take a level each
+        let nested = |depth: usize| format!("print {}1{};", "(".repeat(depth), ")".repeat(depth));
+        for depth in [60, DEFAULT_MAX_DEPTH - 2] {
+            assert!(Parser::new(Scanner::new(&nested(depth)).scan_tokens().unwrap()).parse().is_ok());
+        }
+        assert!(Parser::new(Scanner::new(&nested(DEFAULT_MAX_DEPTH - 1)).scan_tokens().unwrap()).parse().is_err());
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        for source in ["break;", "if (true) continue;", "while (true) { fun f() { break; } }"] {
+            let tokens = Scanner::new(source).scan_tokens().unwrap();
+            assert!(Parser::new(tokens).parse().is_err(), "{} should not parse", source);
+        }
+
+        let tokens = Scanner::new("while (true) { { break; } }").scan_tokens().unwrap();
+        assert!(Parser::new(tokens).parse().is_ok());
+    }
+
+    #[test]
+    fn test_array_and_index() {
+        let tokens = Scanner::new("[1, a][0][b];").scan_tokens().unwrap();
+        let mut statements = Parser::new(tokens).parse().unwrap();
+        let expression = match statements.remove(0) {
+            Stmt::Expression { expression, .. } => expression,
+            _ => panic!("Expected an expression statement."),
+        };
+        assert_eq!(AstPrinter.print(expression).unwrap(), "(index (index (array 1 a) 0) b)");
+
+        let tokens = Scanner::new("[1, 2;").scan_tokens().unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_map_literal_or_block() {
+        let tokens = Scanner::new("var m = {\"a\": 1, \"b\": {}}; { m[\"a\"] = 2; }").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            AstPrinter.print_stmts(&statements).unwrap(),
+            "(var m (map a 1 b (map)))\n(block (; (= (index m a) 2)))"
+        );
+    }
+
+    #[test]
+    fn test_postfix() {
+        let tokens = Scanner::new("-i++; a[0]--;").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        assert_eq!(AstPrinter.print_stmts(&statements).unwrap(), "(; (- (post++ i)))\n(; (post-- (index a 0)))");
+
+        for source in ["1++;", "i++++;", "f()--;", "(i)++;"] {
+            let tokens = Scanner::new(source).scan_tokens().unwrap();
+            match Parser::new(tokens).parse() {
+                Err(Error::ParserError { message, .. }) => assert_eq!(message, "Invalid increment target."),
+                _ => panic!("{} should not parse", source),
+            }
+        }
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let tokens = Scanner::new("x += 1; a[0] *= b -= 2;").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            AstPrinter.print_stmts(&statements).unwrap(),
+            "(; (+= x 1))\n(; (*= (index a 0) (-= b 2)))"
+        );
+
+        let tokens = Scanner::new("1 /= 2;").scan_tokens().unwrap();
+        match Parser::new(tokens).parse() {
+            Err(Error::ParserError { message, .. }) => assert_eq!(message, "Invalid assignment target."),
+            _ => panic!("Expected a parser error."),
+        }
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let tokens = Scanner::new("1 +; 2 +;").scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens.clone());
+        assert!(parser.parse().is_err());
+        assert_eq!(parser.errors().len(), 2);
+
+        let mut parser = Parser::new(tokens).with_strict(true);
+        assert!(parser.parse().is_err());
+        assert_eq!(parser.errors().len(), 1);
+    }
+}
@@ -0,0 +1,97 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    error::CblResult,
+    interpreter::Interpreter,
+    parser::Parser,
+    runner::with_large_stack,
+    scanner::Scanner,
+    token::{Object, Token, TokenType},
+};
+
+/// Read lines from stdin and run them one at a time until Ctrl-D.
+/// A single interpreter is kept alive for the whole session so
+/// variables defined on one line can be used on the next
+pub fn repl() {
+    with_large_stack(|| {
+        let mut interpreter = Interpreter::new();
+        let stdin = io::stdin();
+        let mut lines = stdin.lock();
+
+        loop {
+            print!("> ");
+            io::stdout().flush().ok();
+
+            let mut line = String::new();
+            match lines.read_line(&mut line) {
+                // Ctrl-D
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("{}", e);
+                    break;
+                }
+            }
+
+            match run_line(&mut interpreter, &line) {
+                Ok(Some(value)) => println!("{}", value.repr()),
+                Ok(None) => {}
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+    })
+}
+
+/// Run a single line of input, returning the value of the line when
+/// it is a bare expression. The trailing ';' is optional so that
+/// "x * 2" can be typed instead of "x * 2;"
+pub fn run_line(interpreter: &mut Interpreter, line: &str) -> CblResult<Option<Object>> {
+    let mut scanner = Scanner::new(line);
+    let mut tokens = scanner.scan_tokens()?;
+
+    let eof = tokens.len() - 1;
+    if eof > 0 && !matches!(tokens[eof - 1].type_, TokenType::Semicolon | TokenType::RightBrace) {
+        let last = &tokens[eof];
+        let semicolon = Token::new(
+            TokenType::Semicolon,
+            ";".to_string(),
+            Object::Nil,
+            last.line,
+            last.column,
+        );
+        tokens.insert(eof, semicolon);
+    }
+
+    let mut parser = Parser::new(tokens);
+    let statements = parser.parse()?;
+
+    interpreter.interpret_value(&statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repl_keeps_state() {
+        let mut interpreter = Interpreter::new();
+
+        assert_eq!(run_line(&mut interpreter, "var x = 10;").unwrap(), None);
+        assert_eq!(
+            run_line(&mut interpreter, "x * 2").unwrap(),
+            Some(Object::Int(20))
+        );
+    }
+
+    #[test]
+    fn test_repl_recovers_from_parse_error() {
+        let mut interpreter = Interpreter::new();
+
+        run_line(&mut interpreter, "var x = 10;").unwrap();
+        assert!(run_line(&mut interpreter, "var y = ;").is_err());
+        assert_eq!(
+            run_line(&mut interpreter, "x;").unwrap(),
+            Some(Object::Int(10))
+        );
+    }
+}
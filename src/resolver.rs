@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Expr, Stmt, StmtVisitor, Visitor},
+    error::{CblResult, Error},
+    interpreter::Interpreter,
+    token::{Object, Token},
+};
+
+/// Walks the program once before it runs and tells the interpreter how
+/// many scopes out each local variable lives. Without this a closure
+/// would see variables declared after it in the enclosing block
+pub struct Resolver<'a> {
+    interpreter: &'a mut Interpreter,
+    /// One map per block or function we are inside of, from name to
+    /// whether its initializer has finished. Globals aren't tracked
+    scopes: Vec<HashMap<String, bool>>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(interpreter: &'a mut Interpreter) -> Self {
+        Resolver {
+            interpreter,
+            scopes: Vec::new(),
+        }
+    }
+
+    pub fn resolve(&mut self, statements: &[Stmt]) -> CblResult<()> {
+        for statement in statements {
+            statement.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) -> CblResult<()> {
+        expr.accept(self)
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Add a name to the innermost scope, it can't be read until `define`
+    fn declare(&mut self, name: &Token) -> CblResult<()> {
+        if let Some(scope) = self.scopes.last_mut() {
+            if scope.contains_key(&name.lexeme) {
+                return Err(Error::parser_error(name, "Already a variable with this name in this scope."));
+            }
+            scope.insert(name.lexeme.clone(), false);
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), true);
+        }
+    }
+
+    fn resolve_local(&mut self, id: usize, name: &Token) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if scope.contains_key(&name.lexeme) {
+                self.interpreter.resolve(id, depth);
+                return;
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, params: &[Token], body: &[Stmt]) -> CblResult<()> {
+        self.begin_scope();
+        let result = params
+            .iter()
+            .try_for_each(|param| {
+                self.declare(param)?;
+                self.define(param);
+                Ok(())
+            })
+            .and_then(|_| self.resolve(body));
+        self.end_scope();
+        result
+    }
+}
+
+impl Visitor<()> for Resolver<'_> {
+    fn visit_array_expr(&mut self, elements: &[Expr]) -> CblResult<()> {
+        for element in elements {
+            self.resolve_expr(element)?;
+        }
+        Ok(())
+    }
+
+    fn visit_assign_expr(&mut self, id: usize, name: &Token, value: &Expr) -> CblResult<()> {
+        self.resolve_expr(value)?;
+        self.resolve_local(id, name);
+        Ok(())
+    }
+
+    fn visit_binary_expr(&mut self, left: &Expr, _operator: &Token, right: &Expr) -> CblResult<()> {
+        self.resolve_expr(left)?;
+        self.resolve_expr(right)
+    }
+
+    fn visit_call_expr(&mut self, callee: &Expr, _paren: &Token, arguments: &[Expr]) -> CblResult<()> {
+        self.resolve_expr(callee)?;
+        for argument in arguments {
+            self.resolve_expr(argument)?;
+        }
+        Ok(())
+    }
+
+    fn visit_compound_assign_expr(&mut self, target: &Expr, _operator: &Token, value: &Expr) -> CblResult<()> {
+        self.resolve_expr(value)?;
+        self.resolve_expr(target)
+    }
+
+    fn visit_get_expr(&mut self, object: &Expr, _name: &Token) -> CblResult<()> {
+        self.resolve_expr(object)
+    }
+
+    fn visit_grouping_expr(&mut self, expression: &Expr) -> CblResult<()> {
+        self.resolve_expr(expression)
+    }
+
+    fn visit_index_expr(&mut self, object: &Expr, _bracket: &Token, index: &Expr) -> CblResult<()> {
+        self.resolve_expr(object)?;
+        self.resolve_expr(index)
+    }
+
+    fn visit_index_assign_expr(&mut self, object: &Expr, _bracket: &Token, index: &Expr, value: &Expr) -> CblResult<()> {
+        self.resolve_expr(object)?;
+        self.resolve_expr(index)?;
+        self.resolve_expr(value)
+    }
+
+    fn visit_literal_expr(&mut self, _value: &Object) -> CblResult<()> {
+        Ok(())
+    }
+
+    fn visit_logical_expr(&mut self, left: &Expr, _operator: &Token, right: &Expr) -> CblResult<()> {
+        self.resolve_expr(left)?;
+        self.resolve_expr(right)
+    }
+
+    fn visit_map_expr(&mut self, _brace: &Token, entries: &[(Expr, Expr)]) -> CblResult<()> {
+        for (key, value) in entries {
+            self.resolve_expr(key)?;
+            self.resolve_expr(value)?;
+        }
+        Ok(())
+    }
+
+    fn visit_postfix_expr(&mut self, target: &Expr, _operator: &Token) -> CblResult<()> {
+        self.resolve_expr(target)
+    }
+
+    fn visit_set_expr(&mut self, object: &Expr, _name: &Token, value: &Expr) -> CblResult<()> {
+        self.resolve_expr(value)?;
+        self.resolve_expr(object)
+    }
+
+    fn visit_super_expr(&mut self, id: usize, keyword: &Token, _method: &str) -> CblResult<()> {
+        self.resolve_local(id, keyword);
+        Ok(())
+    }
+
+    fn visit_this_expr(&mut self, id: usize, keyword: &Token) -> CblResult<()> {
+        self.resolve_local(id, keyword);
+        Ok(())
+    }
+
+    fn visit_unary_expr(&mut self, _operator: &Token, right: &Expr) -> CblResult<()> {
+        self.resolve_expr(right)
+    }
+
+    fn visit_variable_expr(&mut self, id: usize, name: &Token) -> CblResult<()> {
+        if let Some(false) = self.scopes.last().and_then(|scope| scope.get(&name.lexeme)) {
+            return Err(Error::parser_error(name, "Can't read local variable in its own initializer."));
+        }
+
+        self.resolve_local(id, name);
+        Ok(())
+    }
+}
+
+impl StmtVisitor<()> for Resolver<'_> {
+    fn visit_assert_stmt(&mut self, _keyword: &Token, condition: &Expr, message: &Option<Expr>) -> CblResult<()> {
+        self.resolve_expr(condition)?;
+        match message {
+            Some(message) => self.resolve_expr(message),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_block_stmt(&mut self, statements: &[Stmt]) -> CblResult<()> {
+        self.begin_scope();
+        let result = self.resolve(statements);
+        self.end_scope();
+        result
+    }
+
+    fn visit_break_stmt(&mut self, _keyword: &Token) -> CblResult<()> {
+        Ok(())
+    }
+
+    fn visit_class_stmt(&mut self, name: &Token, superclass: &Option<Expr>, methods: &[Stmt]) -> CblResult<()> {
+        self.declare(name)?;
+        self.define(name);
+
+        // a subclass's methods see `super` in a scope around the one with `this`
+        if let Some(superclass) = superclass {
+            self.resolve_expr(superclass)?;
+            self.begin_scope();
+            self.scopes.last_mut().unwrap().insert("super".to_string(), true);
+        }
+
+        // methods are bound to an instance in a scope of their own holding `this`
+        self.begin_scope();
+        self.scopes.last_mut().unwrap().insert("this".to_string(), true);
+        let result = methods.iter().try_for_each(|method| match method {
+            Stmt::Function { params, body, .. } => self.resolve_function(params, body),
+            _ => Ok(()),
+        });
+        self.end_scope();
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+        result
+    }
+
+    fn visit_const_stmt(&mut self, name: &Token, initializer: &Expr) -> CblResult<()> {
+        self.declare(name)?;
+        self.resolve_expr(initializer)?;
+        self.define(name);
+        Ok(())
+    }
+
+    fn visit_continue_stmt(&mut self, _keyword: &Token) -> CblResult<()> {
+        Ok(())
+    }
+
+    fn visit_expression_stmt(&mut self, expression: &Expr) -> CblResult<()> {
+        self.resolve_expr(expression)
+    }
+
+    fn visit_for_each_stmt(&mut self, name: &Token, iterable: &Expr, body: &Stmt) -> CblResult<()> {
+        self.resolve_expr(iterable)?;
+
+        self.begin_scope();
+        self.declare(name)?;
+        self.define(name);
+        let result = body.accept(self);
+        self.end_scope();
+        result
+    }
+
+    fn visit_function_stmt(&mut self, name: &Token, params: &[Token], body: &[Stmt]) -> CblResult<()> {
+        // defined straight away so the function can call itself
+        self.declare(name)?;
+        self.define(name);
+        self.resolve_function(params, body)
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> CblResult<()> {
+        self.resolve_expr(condition)?;
+        then_branch.accept(self)?;
+        if let Some(else_branch) = else_branch {
+            else_branch.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_print_stmt(&mut self, expression: &Expr) -> CblResult<()> {
+        self.resolve_expr(expression)
+    }
+
+    fn visit_return_stmt(&mut self, _keyword: &Token, value: &Option<Expr>) -> CblResult<()> {
+        match value {
+            Some(value) => self.resolve_expr(value),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_var_stmt(&mut self, name: &Token, initializer: &Option<Expr>) -> CblResult<()> {
+        self.declare(name)?;
+        if let Some(initializer) = initializer {
+            self.resolve_expr(initializer)?;
+        }
+        self.define(name);
+        Ok(())
+    }
+
+    fn visit_while_stmt(&mut self, condition: &Expr, body: &Stmt, increment: &Option<Expr>) -> CblResult<()> {
+        self.resolve_expr(condition)?;
+        body.accept(self)?;
+        match increment {
+            Some(increment) => self.resolve_expr(increment),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn run(source: &str) -> CblResult<Interpreter> {
+        let tokens = Scanner::new(source).scan_tokens()?;
+        let statements = Parser::new(tokens).parse()?;
+
+        let mut interpreter = Interpreter::new().with_captured_output();
+        interpreter.interpret(&statements)?;
+        Ok(interpreter)
+    }
+
+    #[test]
+    fn test_closure_ignores_later_shadowing() {
+        let source = "
+            var a = \"global\";
+            {
+                fun showA() {
+                    print a;
+                }
+
+                showA();
+                var a = \"block\";
+                showA();
+                print a;
+            }
+        ";
+        let interpreter = run(source).unwrap();
+        assert_eq!(interpreter.output(), ["global", "global", "block"]);
+    }
+
+    #[test]
+    fn test_read_in_own_initializer() {
+        assert!(run("var a = 1; { var a = a + 1; }").is_err());
+        // globals are allowed to refer to a previous definition
+        assert!(run("var a = 1; var a = a + 1;").is_ok());
+    }
+
+    #[test]
+    fn test_redeclare_local() {
+        assert!(run("{ var a = 1; var a = 2; }").is_err());
+        assert!(run("fun f(a, a) {}").is_err());
+    }
+
+    #[test]
+    fn test_this_in_closure() {
+        let source = "
+            class Counter {
+                init() { this.count = 0; }
+                incrementer() {
+                    fun increment() { this.count = this.count + 1; return this.count; }
+                    return increment;
+                }
+            }
+            var increment = Counter().incrementer();
+            increment();
+            print increment();
+        ";
+        let interpreter = run(source).unwrap();
+        assert_eq!(interpreter.output(), ["2"]);
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    fmt::Display,
+    fs,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ast::{ast_to_json, Stmt, TreePrinter}, error::{CblResult, Error, ErrorReporter}, interpreter::Interpreter, parser::Parser, scanner::Scanner,
+};
+
+/// Exit codes follow the sysexits.h convention used by jlox
+pub const EX_OK: i32 = 0;
+pub const EX_USAGE: i32 = 64;
+pub const EX_DATAERR: i32 = 65;
+pub const EX_SOFTWARE: i32 = 70;
+pub const EX_IOERR: i32 = 74;
+
+/// Stack size of the thread scripts are scanned, parsed and run on. A
+/// debug build uses around 30KB of stack per level of nesting, far more
+/// than the 2MB a thread normally gets allows for the default limits
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Run `f` on a new thread with a `STACK_SIZE` stack, waiting for it to
+/// finish
+pub fn with_large_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("Could not start a thread to run the script on")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Run the script at `path`, returning the exit code for the process
+pub fn run_file(path: &str) -> i32 {
+    with_large_stack(|| {
+        let source = match read_source(path) {
+            Ok(source) => source,
+            Err(code) => return code,
+        };
+
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, &source)
+    })
+}
+
+/// Like `run_file`, but also print how long each phase took to stderr
+pub fn time_file(path: &str) -> i32 {
+    with_large_stack(|| {
+        let source = match read_source(path) {
+            Ok(source) => source,
+            Err(code) => return code,
+        };
+
+        let mut interpreter = Interpreter::new();
+        let mut timings = Timings::default();
+        let code = run_timed(&mut interpreter, &source, &mut timings);
+        eprintln!("{}", timings);
+        code
+    })
+}
+
+/// How long each phase of running a script took, a phase that didn't
+/// run because an earlier one failed is left at zero
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timings {
+    pub scan: Duration,
+    pub parse: Duration,
+    pub interpret: Duration,
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "scanned in {}, parsed in {}, interpreted in {}",
+            format_duration(self.scan),
+            format_duration(self.parse),
+            format_duration(self.interpret)
+        )
+    }
+}
+
+/// Milliseconds with a couple of decimal places, eg. "1.25ms"
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Print every token in the script at `path`, one per line, rather
+/// than running it. Returns 65 if any of it couldn't be scanned
+pub fn tokens_file(path: &str) -> i32 {
+    let source = match read_source(path) {
+        Ok(source) => source,
+        Err(code) => return code,
+    };
+
+    let mut reporter = ErrorReporter::new().with_source(&source);
+    for token in Scanner::new(&source) {
+        match token {
+            Ok(token) => println!("{}", token),
+            Err(e) => {
+                reporter.report_error(&e);
+            }
+        }
+    }
+
+    if reporter.had_error() {
+        EX_DATAERR
+    } else {
+        EX_OK
+    }
+}
+
+/// Print the syntax tree of the script at `path` as indented text rather
+/// than running it. Returns 65 if it couldn't be scanned or parsed
+pub fn dump_ast_file(path: &str) -> i32 {
+    print_ast_file(path, |statements| TreePrinter.print_stmts(statements))
+}
+
+/// Print the syntax tree of the script at `path` as a JSON array with
+/// one object per statement, eg. for an external visualizer, rather than
+/// running it. Returns 65 if it couldn't be scanned or parsed
+pub fn ast_json_file(path: &str) -> i32 {
+    print_ast_file(path, ast_to_json)
+}
+
+/// Parse the script at `path` and print what `print` makes of it
+fn print_ast_file(path: &str, print: fn(&[Stmt]) -> CblResult<String>) -> i32 {
+    with_large_stack(|| {
+        let source = match read_source(path) {
+            Ok(source) => source,
+            Err(code) => return code,
+        };
+
+        let mut reporter = ErrorReporter::new().with_source(&source);
+
+        let mut scanner = Scanner::new(&source);
+        let tokens = scanner.scan_tokens();
+        report_all(&mut reporter, scanner.errors());
+
+        if let Ok(tokens) = tokens {
+            let mut parser = Parser::new(tokens);
+            let statements = parser.parse();
+            report_all(&mut reporter, parser.errors());
+
+            if let Ok(statements) = statements {
+                match print(&statements) {
+                    Ok(tree) => println!("{}", tree),
+                    Err(e) => {
+                        reporter.report_error(&e);
+                    }
+                }
+            }
+        }
+
+        if reporter.had_error() {
+            EX_DATAERR
+        } else {
+            EX_OK
+        }
+    })
+}
+
+/// Scan and parse the script at `path` without running it, reporting
+/// any errors and warnings. Returns 65 if it couldn't be scanned or parsed
+pub fn check_file(path: &str) -> i32 {
+    with_large_stack(|| {
+        let source = match read_source(path) {
+            Ok(source) => source,
+            Err(code) => return code,
+        };
+
+        let mut reporter = ErrorReporter::new().with_source(&source);
+
+        let mut scanner = Scanner::new(&source);
+        let tokens = scanner.scan_tokens();
+        report_all(&mut reporter, scanner.errors());
+
+        if let Ok(tokens) = tokens {
+            let mut parser = Parser::new(tokens);
+            // errors are already in `parser.errors()`
+            let _ = parser.parse();
+            report_all(&mut reporter, parser.errors());
+            for warning in parser.warnings() {
+                reporter.report_warning(warning);
+            }
+        }
+
+        if reporter.had_error() {
+            EX_DATAERR
+        } else {
+            EX_OK
+        }
+    })
+}
+
+fn read_source(path: &str) -> Result<String, i32> {
+    fs::read_to_string(path).map_err(|e| {
+        eprintln!("Could not read '{}': {}", path, e);
+        EX_IOERR
+    })
+}
+
+/// Scan, parse and interpret `source`, reporting every error to stderr.
+/// Returns 65 for scanner/parser errors and 70 for runtime errors
+pub fn run(interpreter: &mut Interpreter, source: &str) -> i32 {
+    run_timed(interpreter, source, &mut Timings::default())
+}
+
+/// `run`, recording how long each phase takes in `timings`
+pub fn run_timed(interpreter: &mut Interpreter, source: &str, timings: &mut Timings) -> i32 {
+    let mut reporter = ErrorReporter::new().with_source(source);
+
+    let start = Instant::now();
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens();
+    timings.scan = start.elapsed();
+    report_all(&mut reporter, scanner.errors());
+
+    if let Ok(tokens) = tokens {
+        let start = Instant::now();
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse();
+        timings.parse = start.elapsed();
+        report_all(&mut reporter, parser.errors());
+        for warning in parser.warnings() {
+            reporter.report_warning(warning);
+        }
+
+        if let Ok(statements) = statements {
+            let start = Instant::now();
+            let result = interpreter.interpret(&statements);
+            timings.interpret = start.elapsed();
+            if let Err(e) = result {
+                reporter.report_error(&e);
+            }
+        }
+    }
+
+    if reporter.had_error() {
+        EX_DATAERR
+    } else if reporter.had_runtime_error() {
+        EX_SOFTWARE
+    } else {
+        EX_OK
+    }
+}
+
+fn report_all(reporter: &mut ErrorReporter, errors: &[Error]) {
+    for e in errors {
+        reporter.report_error(e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_script(name: &str, source: &str) -> String {
+        let path = std::env::temp_dir().join(format!("cbl_runner_{}_{}.cbl", std::process::id(), name));
+        fs::write(&path, source).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_warnings_keep_exit_code() {
+        let mut interpreter = Interpreter::new().with_captured_output();
+        assert_eq!(run(&mut interpreter, "fun f() { return 1; print 2; } print f();"), EX_OK);
+        assert_eq!(interpreter.output(), ["1"]);
+    }
+
+    #[test]
+    fn test_run_file_exit_codes() {
+        let ok = write_script("ok", "var a = 1; a = a + 1;");
+        let parse_error = write_script("parse_error", "var a = ;");
+        let scan_error = write_script("scan_error", "var a = @;");
+        let runtime_error = write_script("runtime_error", "var a = -\"a\";");
+
+        assert_eq!(run_file(&ok), EX_OK);
+        assert_eq!(run_file(&parse_error), EX_DATAERR);
+        assert_eq!(run_file(&scan_error), EX_DATAERR);
+        assert_eq!(run_file(&runtime_error), EX_SOFTWARE);
+
+        for path in [ok, parse_error, scan_error, runtime_error] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_run_file_deep_recursion() {
+        // far deeper than a test thread's own stack could take
+        let deep = write_script("deep", "fun f(n) { if (n > 0) return f(n - 1) + 1; return 0; } assert f(450) == 450;");
+        assert_eq!(run_file(&deep), EX_OK);
+        fs::remove_file(deep).unwrap();
+    }
+
+    #[test]
+    fn test_run_file_missing() {
+        assert_eq!(run_file("does/not/exist.cbl"), EX_IOERR);
+        assert_eq!(tokens_file("does/not/exist.cbl"), EX_IOERR);
+        assert_eq!(dump_ast_file("does/not/exist.cbl"), EX_IOERR);
+        assert_eq!(ast_json_file("does/not/exist.cbl"), EX_IOERR);
+        assert_eq!(time_file("does/not/exist.cbl"), EX_IOERR);
+        assert_eq!(check_file("does/not/exist.cbl"), EX_IOERR);
+    }
+
+    #[test]
+    fn test_tokens_file_exit_codes() {
+        let ok = write_script("tokens_ok", "print 1 + 2;");
+        let scan_error = write_script("tokens_scan_error", "print @;");
+
+        assert_eq!(tokens_file(&ok), EX_OK);
+        assert_eq!(tokens_file(&scan_error), EX_DATAERR);
+
+        for path in [ok, scan_error] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_dump_ast_file_exit_codes() {
+        let ok = write_script("dump_ok", "print 1 + 2;");
+        let parse_error = write_script("dump_parse_error", "print 1 +;");
+
+        assert_eq!(dump_ast_file(&ok), EX_OK);
+        assert_eq!(dump_ast_file(&parse_error), EX_DATAERR);
+        assert_eq!(ast_json_file(&ok), EX_OK);
+        assert_eq!(ast_json_file(&parse_error), EX_DATAERR);
+
+        for path in [ok, parse_error] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_check_file_exit_codes() {
+        let ok = write_script("check_ok", "fun f() { return 1; print 2; }");
+        // would fail at runtime, but --check never runs it
+        let runtime_error = write_script("check_runtime_error", "print -\"a\";");
+        let parse_error = write_script("check_parse_error", "print 1 +;");
+        let scan_error = write_script("check_scan_error", "print @;");
+
+        assert_eq!(check_file(&ok), EX_OK);
+        assert_eq!(check_file(&runtime_error), EX_OK);
+        assert_eq!(check_file(&parse_error), EX_DATAERR);
+        assert_eq!(check_file(&scan_error), EX_DATAERR);
+
+        for path in [ok, runtime_error, parse_error, scan_error] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_format_timings() {
+        assert_eq!(format_duration(Duration::from_micros(1250)), "1.25ms");
+        assert_eq!(format_duration(Duration::ZERO), "0.00ms");
+
+        let timings = Timings {
+            scan: Duration::from_millis(2),
+            parse: Duration::from_micros(500),
+            interpret: Duration::from_secs(1),
+        };
+        assert_eq!(timings.to_string(), "scanned in 2.00ms, parsed in 0.50ms, interpreted in 1000.00ms");
+    }
+
+    #[test]
+    fn test_run_timed() {
+        let mut timings = Timings::default();
+        assert_eq!(run_timed(&mut Interpreter::new(), "var a = 1;", &mut timings), EX_OK);
+
+        // phases after a failure don't run
+        let mut timings = Timings::default();
+        assert_eq!(run_timed(&mut Interpreter::new(), "var a = ;", &mut timings), EX_DATAERR);
+        assert_eq!(timings.interpret, Duration::ZERO);
+    }
+}
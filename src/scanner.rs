@@ -1,42 +1,120 @@
-use std::fmt::Display;
+use std::collections::{HashMap, VecDeque};
 
-use crate::token::{Object, Token, TokenType};
+use crate::{
+    error::{CblResult, Error},
+    token::{Object, Token, TokenType},
+};
 
 pub struct Scanner {
-    source: String,
-    tokens: Vec<Token>,
+    /// Decoded once up front so that indexing is O(1)
+    source: Vec<char>,
+    /// Tokens scanned but not handed out by the iterator yet
+    tokens: VecDeque<Token>,
+    errors: Vec<Error>,
+    /// How many of `errors` the iterator has handed out
+    reported_errors: usize,
+    /// Set once the EOF token has been added
+    finished: bool,
+    /// Words that scan as something other than an identifier
+    keywords: HashMap<String, TokenType>,
+    /// Whether comments become `Comment` tokens rather than being skipped
+    trivia: bool,
+    /// For each "${ ... }" we are inside of, the quote of its string and
+    /// how many '{' are open within the embedded expression
+    interpolations: Vec<(char, usize)>,
     start: usize,
     current: usize,
     line: u32,
+    column: u32,
+    /// Line and column where the token currently being scanned began
+    start_line: u32,
+    start_column: u32,
+    /// Byte offsets of `start` and `current`, `source` is indexed by char
+    start_byte: usize,
+    current_byte: usize,
 }
 
 impl Scanner {
     pub fn new(source: &str) -> Scanner {
+        Scanner::with_keywords(source, default_keywords())
+    }
+
+    /// Scan with a different set of keywords, eg. to rename `print` or
+    /// give `else` a second spelling. Start from `default_keywords` to
+    /// only change some of them, any word not in `keywords` is an
+    /// identifier
+    pub fn with_keywords(source: &str, keywords: HashMap<String, TokenType>) -> Scanner {
         Scanner {
-            source: source.to_string(),
-            tokens: vec![],
+            source: source.chars().collect(),
+            tokens: VecDeque::new(),
+            errors: vec![],
+            reported_errors: 0,
+            finished: false,
+            keywords,
+            trivia: false,
+            interpolations: vec![],
             start: 0,
             current: 0,
             line: 1,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
+            start_byte: 0,
+            current_byte: 0,
         }
     }
 
+    /// When `trivia` is set, comments are kept as `Comment` tokens holding
+    /// their text, eg. for a formatter that has to write them back out.
+    /// The parser skips over them
+    pub fn with_trivia(mut self, trivia: bool) -> Scanner {
+        self.trivia = trivia;
+        self
+    }
+
     /// Scan its way through the source file then append one
-    /// final EOF token
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
-        while !self.is_at_end(self.current) {
-            self.start = self.current;
-            self.scan_token();
+    /// final EOF token. Scanning carries on past errors so that
+    /// all of them are recorded, but if there were any the first
+    /// one is returned instead of the tokens. It always scans from the
+    /// start of the source, so calling it again gives the same result
+    /// even if the scanner has already been used as an iterator
+    pub fn scan_tokens(&mut self) -> CblResult<Vec<Token>> {
+        self.rewind();
+
+        // errors are kept in `self.errors` as well, so only the tokens
+        // need collecting here
+        let tokens = self.by_ref().filter_map(Result::ok).collect();
+
+        match self.errors.first() {
+            Some(error) => Err(error.clone()),
+            None => Ok(tokens),
         }
+    }
 
-        self.tokens.push(Token::new(
-            TokenType::Eof,
-            "".to_string(),
-            Object::Nil,
-            self.line,
-        ));
+    /// Go back to the start of the source, forgetting what was scanned
+    fn rewind(&mut self) {
+        self.tokens.clear();
+        self.errors.clear();
+        self.reported_errors = 0;
+        self.finished = false;
+        self.interpolations.clear();
+        self.start = 0;
+        self.current = 0;
+        self.line = 1;
+        self.column = 1;
+        self.start_line = 1;
+        self.start_column = 1;
+        self.start_byte = 0;
+        self.current_byte = 0;
+    }
 
-        self.tokens.clone()
+    /// Every error encountered while scanning, in source order
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    fn error(&mut self, line: u32, column: u32, offset: usize, message: &str) {
+        self.errors.push(Error::scanner_error(line, column, offset, message));
     }
 
     /// Check if the scanner has reached the end of the source file
@@ -51,14 +129,80 @@ impl Scanner {
             match c {
                 '(' => self.add_token(TokenType::LeftParen),
                 ')' => self.add_token(TokenType::RightParen),
-                '{' => self.add_token(TokenType::LeftBrace),
-                '}' => self.add_token(TokenType::RightBrace),
+                '{' => {
+                    if let Some((_, depth)) = self.interpolations.last_mut() {
+                        *depth += 1;
+                    }
+                    self.add_token(TokenType::LeftBrace);
+                }
+                '}' => match self.interpolations.last_mut() {
+                    // the end of an embedded expression, carry on with the string
+                    Some((quote, 0)) => {
+                        let quote = *quote;
+                        self.interpolations.pop();
+                        self.string(quote);
+                    }
+                    Some((_, depth)) => {
+                        *depth -= 1;
+                        self.add_token(TokenType::RightBrace);
+                    }
+                    None => self.add_token(TokenType::RightBrace),
+                },
+                '[' => self.add_token(TokenType::LeftBracket),
+                ']' => self.add_token(TokenType::RightBracket),
+                ':' => self.add_token(TokenType::Colon),
+                '?' => {
+                    let type_ = if self.match_char('?') {
+                        TokenType::QuestionQuestion
+                    } else {
+                        TokenType::Question
+                    };
+                    self.add_token(type_);
+                }
                 ',' => self.add_token(TokenType::Comma),
-                '.' => self.add_token(TokenType::Dot),
-                '-' => self.add_token(TokenType::Minus),
-                '+' => self.add_token(TokenType::Plus),
+                '.' => {
+                    // numbers are allowed to start with their decimal point, eg. ".5"
+                    if self.is_digit(self.peek()) {
+                        self.number();
+                    } else {
+                        self.add_token(TokenType::Dot);
+                    }
+                }
+                '-' => {
+                    let type_ = if self.match_char('-') {
+                        TokenType::MinusMinus
+                    } else if self.match_char('=') {
+                        TokenType::MinusEqual
+                    } else {
+                        TokenType::Minus
+                    };
+                    self.add_token(type_);
+                }
+                '+' => {
+                    let type_ = if self.match_char('+') {
+                        TokenType::PlusPlus
+                    } else if self.match_char('=') {
+                        TokenType::PlusEqual
+                    } else {
+                        TokenType::Plus
+                    };
+                    self.add_token(type_);
+                }
                 ';' => self.add_token(TokenType::Semicolon),
-                '*' => self.add_token(TokenType::Star),
+                '*' => {
+                    let type_ = if self.match_char('*') {
+                        TokenType::StarStar
+                    } else if self.match_char('=') {
+                        TokenType::StarEqual
+                    } else {
+                        TokenType::Star
+                    };
+                    self.add_token(type_);
+                }
+                '%' => self.add_token(TokenType::Percent),
+                '&' => self.add_token(TokenType::Ampersand),
+                '|' => self.add_token(TokenType::Pipe),
+                '^' => self.add_token(TokenType::Caret),
                 '!' => {
                     let type_ = if self.match_char('=') {
                         TokenType::BangEqual
@@ -78,6 +222,8 @@ impl Scanner {
                 '<' => {
                     let type_ = if self.match_char('=') {
                         TokenType::LessEqual
+                    } else if self.match_char('<') {
+                        TokenType::LessLess
                     } else {
                         TokenType::Less
                     };
@@ -86,6 +232,8 @@ impl Scanner {
                 '>' => {
                     let type_ = if self.match_char('=') {
                         TokenType::GreaterEqual
+                    } else if self.match_char('>') {
+                        TokenType::GreaterGreater
                     } else {
                         TokenType::Greater
                     };
@@ -95,34 +243,62 @@ impl Scanner {
                     // this represents a comment and we should ignore
                     // until we see a newline character
                     if self.match_char('/') {
-                        while self.peek() != '\n' && !self.is_at_end(self.current) {
+                        while !matches!(self.peek(), '\n' | '\r') && !self.is_at_end(self.current) {
                             self.advance();
                         }
+                        if self.trivia {
+                            self.add_token(TokenType::Comment);
+                        }
+                    } else if self.match_char('*') {
+                        self.block_comment();
+                    } else if self.match_char('=') {
+                        self.add_token(TokenType::SlashEqual);
                     } else {
                         self.add_token(TokenType::Slash);
                     }
                 }
                 // ignore whitespace
                 ' ' | '\r' | '\t' | '\n' => {}
-                '"' => self.string(),
+                '"' | '\'' => self.string(c),
                 _ => {
-                    if self.is_digit(c) {
+                    if c == '0' && matches!(self.peek(), 'x' | 'X') {
+                        self.radix_number(16);
+                    } else if c == '0' && matches!(self.peek(), 'b' | 'B') {
+                        self.radix_number(2);
+                    } else if self.is_digit(c) {
                         self.number();
                     } else if self.is_alpha(c) {
                         self.identifier();
                     } else {
-                        println!("Unexpected character: {}", c);
-                        println!("char value {}", c as u32)
+                        let message = format!("Unexpected character '{}'.", c);
+                        self.error(self.start_line, self.start_column, self.start_byte, &message);
                     }
                 }
             }
         }
     }
 
-    /// Advance the scanner one character
+    /// Advance the scanner one character, keeping the line
+    /// and column up to date. "\r\n" counts as one line break and so
+    /// does a "\r" on its own
     fn advance(&mut self) -> Option<char> {
+        let c = self.source.get(self.current).copied();
         self.current += 1;
-        self.source.chars().nth(self.current - 1)
+        self.current_byte += c.map_or(0, char::len_utf8);
+
+        if c == Some('\n') || (c == Some('\r') && self.peek() != '\n') {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        c
+    }
+
+    /// Collect the characters in `start..end` into a String
+    fn substring(&self, start: usize, end: usize) -> String {
+        self.source[start..end].iter().collect()
     }
 
     fn add_token(&mut self, type_: TokenType) {
@@ -130,9 +306,11 @@ impl Scanner {
     }
 
     fn add_token_literal(&mut self, type_: TokenType, literal: Object) {
-        let text = self.source[self.start..self.current].to_string();
-        self.tokens
-            .push(Token::new(type_, text, literal, self.line));
+        let text = self.substring(self.start, self.current);
+        self.tokens.push_back(
+            Token::new(type_, text, literal, self.start_line, self.start_column)
+                .with_span(self.start_byte..self.current_byte),
+        );
     }
 
     /// Check if the current character matches the expected character
@@ -141,14 +319,11 @@ impl Scanner {
             return false;
         }
 
-        let opt_c = self.source.chars().nth(self.current);
-        if let Some(c) = opt_c {
-            if c != expected {
-                return false;
-            }
+        if self.source[self.current] != expected {
+            return false;
         }
 
-        self.current += 1;
+        self.advance();
         true
     }
 
@@ -158,7 +333,7 @@ impl Scanner {
             return '\0';
         }
 
-        self.source.chars().nth(self.current).unwrap()
+        self.source[self.current]
     }
 
     /// Look at the character after the next character without advancing the scanner
@@ -167,95 +342,299 @@ impl Scanner {
             return '\0';
         }
 
-        self.source.chars().nth(self.current + 1).unwrap()
+        self.source[self.current + 1]
     }
 
-    /// Store all of the characters between '"' and '"'
-    fn string(&mut self) {
-        while self.peek() != '"' && !self.is_at_end(self.current) {
-            if self.peek() == '\n' {
-                self.line += 1;
+    /// Skip over a "/* ... */" comment, the opening "/*" has already
+    /// been consumed. Comments can be nested, so "/* /* */ */" is a
+    /// single comment
+    fn block_comment(&mut self) {
+        let mut depth = 1;
+
+        while depth > 0 {
+            if self.is_at_end(self.current) {
+                self.error(self.start_line, self.start_column, self.start_byte, "Unterminated block comment.");
+                return;
+            }
+
+            if self.peek() == '/' && self.peek_next() == '*' {
+                self.advance();
+                self.advance();
+                depth += 1;
+            } else if self.peek() == '*' && self.peek_next() == '/' {
+                self.advance();
+                self.advance();
+                depth -= 1;
+            } else {
+                self.advance();
+            }
+        }
+
+        if self.trivia {
+            self.add_token(TokenType::Comment);
+        }
+    }
+
+    /// Store all of the characters between '"' and '"', replacing
+    /// escape sequences like "\n" with the character they stand for
+    /// Strings can be delimited by either `"` or `'`, the other kind of
+    /// quote is an ordinary character inside of them. A "${" stops the
+    /// string early with an Interpolation token, see `scan_token` for
+    /// how the rest of it is picked up again
+    fn string(&mut self, quote: char) {
+        let mut value = String::new();
+
+        while self.peek() != quote && !self.is_at_end(self.current) {
+            if self.peek() == '$' && self.peek_next() == '{' {
+                self.advance();
+                self.advance();
+                self.interpolations.push((quote, 0));
+                self.add_token_literal(TokenType::Interpolation, Object::String(value));
+                return;
+            }
+
+            match self.advance() {
+                Some('\\') if !self.is_at_end(self.current) => {
+                    if let Some(c) = self.escape() {
+                        value.push(c);
+                    }
+                }
+                Some(c) => value.push(c),
+                None => break,
             }
-            self.advance();
         }
 
         if self.is_at_end(self.current) {
-            println!("Unterminated string");
+            self.error(
+                self.start_line,
+                self.start_column,
+                self.start_byte,
+                &format!("Unterminated string starting on line {}.", self.start_line),
+            );
             return;
         }
 
-        // consume the closing "
+        // consume the closing quote
         self.advance();
 
-        let value = self.source[self.start + 1..self.current - 1].to_string();
         self.add_token_literal(TokenType::String, Object::String(value));
     }
 
+    /// Translate the character following a '\\' inside of a string
+    fn escape(&mut self) -> Option<char> {
+        // the '\\' has just been consumed, and `c` may be a line break
+        // which moves the line and column on
+        let (line, column, offset) = (self.line, self.column - 1, self.current_byte - 1);
+        match self.advance() {
+            Some('n') => Some('\n'),
+            Some('t') => Some('\t'),
+            Some('r') => Some('\r'),
+            Some('0') => Some('\0'),
+            Some('\\') => Some('\\'),
+            Some('"') => Some('"'),
+            Some('\'') => Some('\''),
+            Some('$') => Some('$'),
+            Some(c) => {
+                // escaped so that a line break doesn't split the message
+                let message = format!("Unknown escape sequence '\\{}'.", c.escape_debug());
+                self.error(line, column, offset, &message);
+                None
+            }
+            None => None,
+        }
+    }
+
     fn is_digit(&self, c: char) -> bool {
-        '0' <= c && c <= '9'
+        c.is_ascii_digit()
     }
 
+    /// Any Unicode letter can start an identifier, eg. "café"
     fn is_alpha(&self, c: char) -> bool {
-        ('a' <= c && c <= 'z') || ('A' <= c && c <= 'Z') || c == '_'
+        c.is_alphabetic() || c == '_'
     }
 
     fn is_alpha_numeric(&self, c: char) -> bool {
-        self.is_alpha(c) || self.is_digit(c)
+        c.is_alphanumeric() || c == '_'
     }
 
     /// Store all of the characters between '0' and '9'
+    /// Also handles a leading '.' (".5"), an exponent ("1.5e-3") and
+    /// underscores between digits ("1_000")
     fn number(&mut self) {
-        while self.is_digit(self.peek()) {
+        let mut valid = self.digits();
+
+        // look for a fractional part, unless the number began with one
+        let leading_dot = self.source[self.start] == '.';
+        if !leading_dot && self.peek() == '.' && self.is_digit(self.peek_next()) {
+            // consume the '.'
             self.advance();
+
+            valid &= self.digits();
         }
 
-        // look for a fractional part
-        if self.peek() == '.' && self.is_digit(self.peek_next()) {
-            // consume the '.'
+        // look for an exponent
+        if self.peek() == 'e' || self.peek() == 'E' {
             self.advance();
 
-            while self.is_digit(self.peek()) {
+            if self.peek() == '+' || self.peek() == '-' {
                 self.advance();
             }
+
+            if !self.is_digit(self.peek()) {
+                self.error(self.start_line, self.start_column, self.start_byte, "Expect digits in exponent.");
+                return;
+            }
+
+            valid &= self.digits();
         }
 
-        let value = self.source[self.start..self.current]
-            .parse::<f64>()
-            .unwrap();
-        self.add_token_literal(TokenType::Number, Object::Number(value));
+        if !valid {
+            let message = "Underscores in numbers must be between two digits.";
+            self.error(self.start_line, self.start_column, self.start_byte, message);
+            return;
+        }
+
+        // without a fraction or exponent it's an int, unless it's too
+        // big to fit in which case it falls back to a float
+        let text = self.substring(self.start, self.current).replace('_', "");
+        let value = match text.parse::<i64>() {
+            Ok(value) => Object::Int(value),
+            Err(_) => Object::Number(text.parse::<f64>().unwrap()),
+        };
+        self.add_token_literal(TokenType::Number, value);
+    }
+
+    /// Store an integer written in another base, eg. "0xFF" or "0b1010".
+    /// The leading '0' has been consumed but the 'x' / 'b' has not
+    fn radix_number(&mut self, radix: u32) {
+        self.advance();
+
+        // take everything that could belong to the literal so that "0xG"
+        // is reported as one bad number rather than "0x" then "G"
+        while self.is_alpha_numeric(self.peek()) {
+            self.advance();
+        }
+
+        let text = self.substring(self.start + 2, self.current);
+        let valid_underscores = !text.starts_with('_') && !text.ends_with('_') && !text.contains("__");
+        let digits = text.replace('_', "");
+
+        if digits.is_empty() || !valid_underscores || !digits.chars().all(|c| c.is_digit(radix)) {
+            let message = format!("Invalid digits in base {} number '{}'.", radix, self.substring(self.start, self.current));
+            self.error(self.start_line, self.start_column, self.start_byte, &message);
+            return;
+        }
+
+        match u64::from_str_radix(&digits, radix) {
+            Ok(value) => {
+                let value = i64::try_from(value).map_or(Object::Number(value as f64), Object::Int);
+                self.add_token_literal(TokenType::Number, value);
+            }
+            Err(_) => self.error(self.start_line, self.start_column, self.start_byte, "Number literal is too large."),
+        }
+    }
+
+    /// Consume a run of digits, which may be separated by underscores.
+    /// Returns false if any underscore wasn't between two digits
+    fn digits(&mut self) -> bool {
+        let mut valid = true;
+
+        while self.is_digit(self.peek()) || self.peek() == '_' {
+            if self.peek() == '_' {
+                let after_digit = self.current > 0 && self.is_digit(self.source[self.current - 1]);
+                valid &= after_digit && self.is_digit(self.peek_next());
+            }
+            self.advance();
+        }
+
+        valid
     }
 
     /// Store all of the characters between 'a' and 'z' or 'A' and 'Z'
     fn identifier(&mut self) {
-        while self.is_alpha(self.peek()) || self.is_digit(self.peek()) {
+        while self.is_alpha_numeric(self.peek()) {
             self.advance();
         }
 
-        let text = self.source[self.start..self.current].to_string();
-        let type_ = match text.as_str() {
-            "and" => TokenType::And,
-            "class" => TokenType::Class,
-            "else" => TokenType::Else,
-            "false" => TokenType::False,
-            "for" => TokenType::For,
-            "fun" => TokenType::Fun,
-            "if" => TokenType::If,
-            "nil" => TokenType::Nil,
-            "or" => TokenType::Or,
-            "print" => TokenType::Print,
-            "return" => TokenType::Return,
-            "super" => TokenType::Super,
-            "this" => TokenType::This,
-            "true" => TokenType::True,
-            "var" => TokenType::Var,
-            "while" => TokenType::While,
-            _ => TokenType::Identifier,
-        };
+        let text = self.substring(self.start, self.current);
+        let type_ = self.keywords.get(&text).cloned().unwrap_or(TokenType::Identifier);
 
         self.add_token(type_);
     }
 }
 
+/// The keywords `Scanner::new` uses
+pub fn default_keywords() -> HashMap<String, TokenType> {
+    [
+        ("and", TokenType::And),
+        ("assert", TokenType::Assert),
+        ("break", TokenType::Break),
+        ("class", TokenType::Class),
+        ("const", TokenType::Const),
+        ("continue", TokenType::Continue),
+        ("else", TokenType::Else),
+        ("false", TokenType::False),
+        ("for", TokenType::For),
+        ("fun", TokenType::Fun),
+        ("if", TokenType::If),
+        ("in", TokenType::In),
+        ("nil", TokenType::Nil),
+        ("or", TokenType::Or),
+        ("print", TokenType::Print),
+        ("return", TokenType::Return),
+        ("super", TokenType::Super),
+        ("this", TokenType::This),
+        ("true", TokenType::True),
+        ("var", TokenType::Var),
+        ("while", TokenType::While),
+    ]
+    .into_iter()
+    .map(|(word, type_)| (word.to_string(), type_))
+    .collect()
+}
+
+/// Scans lazily, one token at a time, so a large source doesn't need
+/// all of its tokens in memory at once. Errors are yielded in place of
+/// the token they happened in and the last item is always the EOF token
+impl Iterator for Scanner {
+    type Item = CblResult<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.reported_errors < self.errors.len() {
+                self.reported_errors += 1;
+                return Some(Err(self.errors[self.reported_errors - 1].clone()));
+            }
+
+            if let Some(token) = self.tokens.pop_front() {
+                return Some(Ok(token));
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            if self.is_at_end(self.current) {
+                if !self.interpolations.is_empty() {
+                    self.interpolations.clear();
+                    self.error(self.line, self.column, self.current_byte, "Unterminated string interpolation.");
+                }
+                self.tokens.push_back(
+                    Token::new(TokenType::Eof, "".to_string(), Object::Nil, self.line, self.column)
+                        .with_span(self.current_byte..self.current_byte),
+                );
+                self.finished = true;
+            } else {
+                self.start = self.current;
+                self.start_line = self.line;
+                self.start_column = self.column;
+                self.start_byte = self.current_byte;
+                self.scan_token();
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -263,15 +642,440 @@ mod tests {
     #[test]
     fn test_scanner() {
         let mut scanner = Scanner::new("-123");
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
     
         let expected = vec![
-            Token::new(TokenType::Minus, String::from("-"), Object::Nil, 1),
-            Token::new(TokenType::Number, String::from("123"), Object::Number(123.0), 1),
-            Token::new(TokenType::Eof, String::from(""), Object::Nil, 1),
+            Token::new(TokenType::Minus, String::from("-"), Object::Nil, 1, 1).with_span(0..1),
+            Token::new(TokenType::Number, String::from("123"), Object::Int(123), 1, 2).with_span(1..4),
+            Token::new(TokenType::Eof, String::from(""), Object::Nil, 1, 5).with_span(4..4),
         ];
     
         assert_eq!(tokens, expected);
     }
-    
-}
\ No newline at end of file
+
+    #[test]
+    fn test_scanner_multibyte_characters() {
+        let mut scanner = Scanner::new("\"héllo\" + 1");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens[0].literal, Object::String("héllo".to_string()));
+        assert_eq!(tokens[1].type_, TokenType::Plus);
+        assert_eq!(tokens[2].literal, Object::Int(1));
+    }
+
+    #[test]
+    fn test_scanner_large_input() {
+        let source = "var a = 1;\n".repeat(100_000);
+        let mut scanner = Scanner::new(&source);
+        let tokens = scanner.scan_tokens().unwrap();
+
+        // 5 tokens per line plus the trailing EOF
+        assert_eq!(tokens.len(), 500_001);
+        assert_eq!(tokens[tokens.len() - 1].line, 100_001);
+    }
+
+    #[test]
+    fn test_scanner_columns() {
+        let mut scanner = Scanner::new("12 * 34");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens[1].type_, TokenType::Star);
+        assert_eq!((tokens[1].line, tokens[1].column), (1, 4));
+        assert_eq!((tokens[2].line, tokens[2].column), (1, 6));
+    }
+
+    #[test]
+    fn test_scanner_columns_reset_on_newline() {
+        let mut scanner = Scanner::new("1\n  22");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!((tokens[1].line, tokens[1].column), (2, 3));
+    }
+
+    #[test]
+    fn test_scanner_crlf_lines() {
+        let mut scanner = Scanner::new("var a = 1; // one\r\n\"two\r\n\" b;\r\n  c;");
+        let tokens = scanner.scan_tokens().unwrap();
+        let positions = tokens
+            .iter()
+            .map(|token| (token.lexeme.as_str(), token.line, token.column))
+            .collect::<Vec<(&str, u32, u32)>>();
+        assert_eq!(positions[5..], [
+            ("\"two\r\n\"", 2, 1),
+            ("b", 3, 3),
+            (";", 3, 4),
+            ("c", 4, 3),
+            (";", 4, 4),
+            ("", 4, 5),
+        ]);
+
+        // a lone '\r' also ends the line, and the comment
+        let mut scanner = Scanner::new("// one\ra\rb");
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!((tokens[0].line, tokens[1].line), (2, 3));
+    }
+
+    #[test]
+    fn test_scanner_block_comment() {
+        let mut scanner = Scanner::new("1 /* comment */ + 2");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let types: Vec<TokenType> = tokens.into_iter().map(|t| t.type_).collect();
+        assert_eq!(
+            types,
+            vec![TokenType::Number, TokenType::Plus, TokenType::Number, TokenType::Eof]
+        );
+    }
+
+    #[test]
+    fn test_scanner_nested_block_comment() {
+        let mut scanner = Scanner::new("/* outer /* inner\n */ still outer */ 1");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].literal, Object::Int(1));
+        assert_eq!(tokens[0].line, 2);
+    }
+
+    #[test]
+    fn test_scanner_unterminated_block_comment() {
+        let mut scanner = Scanner::new("1 /* never closed");
+
+        assert!(scanner.scan_tokens().is_err());
+        assert!(matches!(scanner.errors(), [Error::ScannerError { line: 1, column: 3, .. }]));
+    }
+
+    #[test]
+    fn test_scanner_string_escapes() {
+        let mut scanner = Scanner::new(r#""a\tb""#);
+        let tokens = scanner.scan_tokens().unwrap();
+
+        match &tokens[0].literal {
+            Object::String(s) => assert_eq!(s.chars().count(), 3),
+            _ => panic!("Expected a string literal."),
+        }
+    }
+
+    #[test]
+    fn test_scanner_escaped_quote() {
+        let mut scanner = Scanner::new(r#""say \"hi\"\\" 1"#);
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens[0].literal, Object::String("say \"hi\"\\".to_string()));
+        assert_eq!(tokens[1].literal, Object::Int(1));
+    }
+
+    #[test]
+    fn test_scanner_unknown_escape() {
+        let mut scanner = Scanner::new(r#""a\qb""#);
+
+        assert!(scanner.scan_tokens().is_err());
+        assert!(matches!(scanner.errors(), [Error::ScannerError { line: 1, column: 3, .. }]));
+    }
+
+    #[test]
+    fn test_scanner_backslash_at_end_of_line() {
+        for source in ["print \"a\\\nb\";", "print \"a\\\r\nb\";", "print \"a\\\rb\";"] {
+            let mut scanner = Scanner::new(source);
+
+            assert!(scanner.scan_tokens().is_err());
+            assert!(matches!(scanner.errors(), [Error::ScannerError { line: 1, column: 9, .. }]));
+        }
+    }
+
+    #[test]
+    fn test_scanner_unterminated_string() {
+        let mut scanner = Scanner::new("\"abc");
+
+        match scanner.scan_tokens() {
+            Err(Error::ScannerError { message, line, .. }) => {
+                assert_eq!(line, 1);
+                assert!(message.contains("line 1"));
+            }
+            _ => panic!("Expected a scanner error."),
+        }
+    }
+
+    #[test]
+    fn test_scanner_collects_unexpected_characters() {
+        let mut scanner = Scanner::new("@#$");
+
+        assert!(scanner.scan_tokens().is_err());
+
+        let columns: Vec<u32> = scanner
+            .errors()
+            .iter()
+            .map(|e| match e {
+                Error::ScannerError { column, .. } => *column,
+                _ => panic!("Expected a scanner error."),
+            })
+            .collect();
+        assert_eq!(columns, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_scanner_scientific_notation() {
+        let mut scanner = Scanner::new("6.022e23 1.5e-3 1E+2");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[0].literal, Object::Number(6.022e23));
+        assert_eq!(tokens[1].literal, Object::Number(1.5e-3));
+        assert_eq!(tokens[2].literal, Object::Number(100.0));
+    }
+
+    #[test]
+    fn test_scanner_leading_dot() {
+        let mut scanner = Scanner::new(".5");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].literal, Object::Number(0.5));
+    }
+
+    #[test]
+    fn test_scanner_missing_exponent() {
+        let mut scanner = Scanner::new("1e");
+        assert!(scanner.scan_tokens().is_err());
+
+        let mut scanner = Scanner::new("1e+;");
+        assert!(scanner.scan_tokens().is_err());
+    }
+
+    #[test]
+    fn test_scanner_number_underscores() {
+        let mut scanner = Scanner::new("1_000 1_000.000_1 1e1_0");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens[0].literal, Object::Int(1000));
+        assert_eq!(tokens[1].literal, Object::Number(1000.0001));
+        assert_eq!(tokens[2].literal, Object::Number(1e10));
+    }
+
+    #[test]
+    fn test_scanner_misplaced_number_underscores() {
+        for source in ["1__0", "1_", "1_.5", "1.5_"] {
+            let mut scanner = Scanner::new(source);
+            assert!(scanner.scan_tokens().is_err(), "{} should not scan", source);
+            assert_eq!(scanner.errors().len(), 1);
+        }
+
+        // a leading underscore makes an identifier rather than a number
+        let mut scanner = Scanner::new("_5");
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[0].type_, TokenType::Identifier);
+    }
+
+    #[test]
+    fn test_scanner_hex_and_binary() {
+        let mut scanner = Scanner::new("0xFF 0b1010 0XdEaD_bEeF 0");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens[0].literal, Object::Int(255));
+        assert_eq!(tokens[1].literal, Object::Int(10));
+        assert_eq!(tokens[2].literal, Object::Int(3735928559));
+        assert_eq!(tokens[3].literal, Object::Int(0));
+    }
+
+    #[test]
+    fn test_scanner_invalid_radix_digits() {
+        for source in ["0xG", "0b102", "0x", "0x_1"] {
+            let mut scanner = Scanner::new(source);
+            assert!(scanner.scan_tokens().is_err(), "{} should not scan", source);
+            assert_eq!(scanner.errors().len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_scanner_iterator() {
+        let source = "var a = 1; // comment\nprint a * 2.5;";
+        let expected = Scanner::new(source).scan_tokens().unwrap();
+        let streamed = Scanner::new(source).collect::<CblResult<Vec<Token>>>().unwrap();
+        assert_eq!(streamed, expected);
+        assert_eq!(streamed.last().unwrap().type_, TokenType::Eof);
+
+        let mut scanner = Scanner::new("1 @ 2");
+        assert!(matches!(scanner.next(), Some(Ok(_))));
+        assert!(matches!(scanner.next(), Some(Err(Error::ScannerError { .. }))));
+        assert!(matches!(scanner.next(), Some(Ok(_))));
+        assert!(matches!(scanner.next(), Some(Ok(Token { type_: TokenType::Eof, .. }))));
+        assert!(scanner.next().is_none());
+    }
+
+    #[test]
+    fn test_scanner_single_quoted_strings() {
+        let mut scanner = Scanner::new("'hi' \"it's\" 'say \"hi\"' 'don\\'t'");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens[0].literal, Object::String("hi".to_string()));
+        assert_eq!(tokens[1].literal, Object::String("it's".to_string()));
+        assert_eq!(tokens[2].literal, Object::String("say \"hi\"".to_string()));
+        assert_eq!(tokens[3].literal, Object::String("don't".to_string()));
+
+        assert!(Scanner::new("'unterminated").scan_tokens().is_err());
+    }
+
+    #[test]
+    fn test_scanner_interpolation() {
+        let mut scanner = Scanner::new("\"a ${x + 1} b ${ {} } c\"");
+        let types = scanner
+            .scan_tokens()
+            .unwrap()
+            .iter()
+            .map(|token| token.type_.clone())
+            .collect::<Vec<TokenType>>();
+
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Interpolation,
+                TokenType::Identifier,
+                TokenType::Plus,
+                TokenType::Number,
+                TokenType::Interpolation,
+                TokenType::LeftBrace,
+                TokenType::RightBrace,
+                TokenType::String,
+                TokenType::Eof,
+            ]
+        );
+
+        let mut scanner = Scanner::new("'cost: \\${x}'");
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[0].literal, Object::String("cost: ${x}".to_string()));
+
+        assert!(Scanner::new("\"a ${x").scan_tokens().is_err());
+    }
+
+    #[test]
+    fn test_scanner_bitwise_operators() {
+        let mut scanner = Scanner::new("& | ^ << >> < > <= >=");
+        let tokens = scanner.scan_tokens().unwrap();
+        let types = tokens.iter().map(|token| token.type_.clone()).collect::<Vec<TokenType>>();
+
+        assert_eq!(types, vec![
+            TokenType::Ampersand,
+            TokenType::Pipe,
+            TokenType::Caret,
+            TokenType::LessLess,
+            TokenType::GreaterGreater,
+            TokenType::Less,
+            TokenType::Greater,
+            TokenType::LessEqual,
+            TokenType::GreaterEqual,
+            TokenType::Eof,
+        ]);
+    }
+
+    #[test]
+    fn test_scanner_question_and_colon() {
+        let mut scanner = Scanner::new("a ? b : c");
+        let tokens = scanner.scan_tokens().unwrap();
+        let types = tokens.iter().map(|token| token.type_.clone()).collect::<Vec<TokenType>>();
+
+        assert!(scanner.errors().is_empty());
+        assert_eq!(types, vec![
+            TokenType::Identifier,
+            TokenType::Question,
+            TokenType::Identifier,
+            TokenType::Colon,
+            TokenType::Identifier,
+            TokenType::Eof,
+        ]);
+    }
+
+    #[test]
+    fn test_scan_tokens_twice() {
+        let mut scanner = Scanner::new("var a = \"${1}\";\n@");
+        assert!(scanner.scan_tokens().is_err());
+        let errors = scanner.errors().to_vec();
+        assert!(scanner.scan_tokens().is_err());
+        assert_eq!(scanner.errors().len(), 1);
+        assert_eq!(scanner.errors()[0].to_string(), errors[0].to_string());
+
+        let mut scanner = Scanner::new("print 1;");
+        let first = scanner.scan_tokens().unwrap();
+        assert_eq!(first.len(), 4);
+        assert_eq!(scanner.scan_tokens().unwrap(), first);
+
+        // even after being partly used as an iterator
+        let mut scanner = Scanner::new("print 1;");
+        scanner.next();
+        assert_eq!(scanner.scan_tokens().unwrap(), first);
+    }
+
+    #[test]
+    fn test_scanner_question_question() {
+        let tokens = Scanner::new("a ?? b ? c").scan_tokens().unwrap();
+        let types = tokens.iter().map(|token| token.type_.clone()).collect::<Vec<TokenType>>();
+
+        assert_eq!(types, vec![
+            TokenType::Identifier,
+            TokenType::QuestionQuestion,
+            TokenType::Identifier,
+            TokenType::Question,
+            TokenType::Identifier,
+            TokenType::Eof,
+        ]);
+    }
+
+    #[test]
+    fn test_scanner_trivia() {
+        let tokens = Scanner::new("1 // note").scan_tokens().unwrap();
+        assert_eq!(tokens.len(), 2);
+
+        let mut scanner = Scanner::new("1 // note\n/* a\n /* b */ */ 2").with_trivia(true);
+        let tokens = scanner.scan_tokens().unwrap();
+        let lexemes = tokens
+            .iter()
+            .map(|token| (token.type_.clone(), token.lexeme.as_str(), token.line))
+            .collect::<Vec<(TokenType, &str, u32)>>();
+        assert_eq!(lexemes, vec![
+            (TokenType::Number, "1", 1),
+            (TokenType::Comment, "// note", 1),
+            (TokenType::Comment, "/* a\n /* b */ */", 2),
+            (TokenType::Number, "2", 3),
+            (TokenType::Eof, "", 3),
+        ]);
+    }
+
+    #[test]
+    fn test_scanner_custom_keywords() {
+        let mut keywords = default_keywords();
+        keywords.remove("print");
+        keywords.insert("afficher".to_string(), TokenType::Print);
+
+        let mut scanner = Scanner::with_keywords("afficher print;", keywords);
+        let tokens = scanner.scan_tokens().unwrap();
+        let types = tokens.iter().map(|token| token.type_.clone()).collect::<Vec<TokenType>>();
+        assert_eq!(types, vec![TokenType::Print, TokenType::Identifier, TokenType::Semicolon, TokenType::Eof]);
+    }
+
+    #[test]
+    fn test_scanner_unicode_identifiers() {
+        let mut scanner = Scanner::new("var naïve = café_2 + 日本;");
+        let tokens = scanner.scan_tokens().unwrap();
+        let identifiers = tokens
+            .iter()
+            .filter(|token| token.type_ == TokenType::Identifier)
+            .map(|token| token.lexeme.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(identifiers, ["naïve", "café_2", "日本"]);
+        assert_eq!(tokens[3].column, 13);
+    }
+
+    #[test]
+    fn test_scanner_spans() {
+        let source = "x = 123;";
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        assert_eq!(tokens[2].span(), 4..7);
+        assert_eq!(&source[tokens[2].span()], "123");
+
+        // offsets are in bytes, so multibyte characters count more than once
+        let source = "\"é\" + naïve";
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        assert_eq!(tokens[0].span(), 0..4);
+        assert_eq!(&source[tokens[2].span()], "naïve");
+        assert_eq!(tokens[3].span(), source.len()..source.len());
+    }
+}
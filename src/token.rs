@@ -1,11 +1,58 @@
-use std::fmt::Display;
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::HashMap,
+    fmt::Display,
+    hash::{Hash, Hasher},
+    ops::Range,
+    rc::Rc,
+};
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::{
+    callable::Callable,
+    class::{Class, Instance},
+    error::Error,
+};
+
+#[derive(Debug, Clone)]
 pub enum Object {
     Nil,
     Bool(bool),
+    /// Whole numbers written without a fraction or exponent, eg. "3"
+    Int(i64),
     Number(f64),
     String(String),
+    /// Natives like `clock` and functions declared with `fun`
+    Callable(Rc<dyn Callable>),
+    /// A list of values, eg. "[1, 2, 3]". Shared, so changes made through
+    /// one variable are seen through every other
+    Array(Rc<RefCell<Vec<Object>>>),
+    /// Values looked up by string keys, eg. "{"a": 1}". Shared like arrays
+    Map(Rc<RefCell<HashMap<String, Object>>>),
+    /// A class, calling it makes an instance
+    Class(Rc<Class>),
+    /// An object made from a class, with its own fields
+    Instance(Rc<Instance>),
+}
+
+/// Callables, collections, classes and instances are only equal to themselves, two
+/// functions declared the same way are still different functions
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Object::Nil, Object::Nil) => true,
+            (Object::Bool(a), Object::Bool(b)) => a == b,
+            (Object::Int(a), Object::Int(b)) => a == b,
+            (Object::Number(a), Object::Number(b)) => a == b,
+            (Object::String(a), Object::String(b)) => a == b,
+            (Object::Callable(a), Object::Callable(b)) => Rc::ptr_eq(a, b),
+            (Object::Array(a), Object::Array(b)) => Rc::ptr_eq(a, b),
+            (Object::Map(a), Object::Map(b)) => Rc::ptr_eq(a, b),
+            (Object::Class(a), Object::Class(b)) => Rc::ptr_eq(a, b),
+            (Object::Instance(a), Object::Instance(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
 }
 
 impl Display for Object {
@@ -13,10 +60,320 @@ impl Display for Object {
         match self {
             Object::Nil => write!(f, "nil"),
             Object::Bool(b) => write!(f, "{}", b),
-            Object::Number(n) => write!(f, "{}", n),
+            Object::Int(n) => write!(f, "{}", n),
+            Object::Number(n) => format_number(f, *n),
             Object::String(s) => write!(f, "{}", s),
+            Object::Callable(callable) => write!(f, "{:?}", callable),
+            Object::Array(_) | Object::Map(_) => write_nested(f, self, &mut Vec::new()),
+            Object::Class(class) => write!(f, "{:?}", class),
+            Object::Instance(instance) => write!(f, "{:?}", instance),
+        }
+    }
+}
+
+/// Write a value that may be inside an array or map. Strings are quoted
+/// so ["a, b"] can be told apart from ["a", "b"], and map keys are
+/// sorted so the output doesn't change from run to run. `seen` holds the
+/// collections currently being written, one that contains itself is
+/// written as "[...]" or "{...}" instead of recursing forever
+fn write_nested(f: &mut std::fmt::Formatter<'_>, value: &Object, seen: &mut Vec<*const ()>) -> std::fmt::Result {
+    match value {
+        Object::String(s) => write!(f, "{}", json_string(s)),
+        Object::Array(elements) => {
+            let ptr = Rc::as_ptr(elements).cast::<()>();
+            if seen.contains(&ptr) {
+                return write!(f, "[...]");
+            }
+
+            seen.push(ptr);
+            write!(f, "[")?;
+            for (i, element) in elements.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_nested(f, element, seen)?;
+            }
+            seen.pop();
+            write!(f, "]")
+        }
+        Object::Map(entries) => {
+            let ptr = Rc::as_ptr(entries).cast::<()>();
+            if seen.contains(&ptr) {
+                return write!(f, "{{...}}");
+            }
+
+            seen.push(ptr);
+            let entries = entries.borrow();
+            let mut keys = entries.keys().collect::<Vec<&String>>();
+            keys.sort();
+            write!(f, "{{")?;
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}: ", json_string(key))?;
+                write_nested(f, &entries[key], seen)?;
+            }
+            seen.pop();
+            write!(f, "}}")
+        }
+        value => write!(f, "{}", value),
+    }
+}
+
+/// Only values of the same kind can be ordered: numbers by value and
+/// strings lexicographically, anything else gives None. An int and a
+/// float with the same value aren't `==`, so the int is put first
+impl PartialOrd for Object {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Object::Nil, Object::Nil) => Some(Ordering::Equal),
+            (Object::Bool(a), Object::Bool(b)) => a.partial_cmp(b),
+            (Object::Int(a), Object::Int(b)) => a.partial_cmp(b),
+            (Object::Number(a), Object::Number(b)) => a.partial_cmp(b),
+            (Object::Int(a), Object::Number(b)) => {
+                (*a as f64).partial_cmp(b).map(|o| o.then(Ordering::Less))
+            }
+            (Object::Number(a), Object::Int(b)) => {
+                a.partial_cmp(&(*b as f64)).map(|o| o.then(Ordering::Greater))
+            }
+            (Object::String(a), Object::String(b)) => a.partial_cmp(b),
+            _ if self == other => Some(Ordering::Equal),
+            _ => None,
+        }
+    }
+}
+
+/// Lets objects be used as map keys. Functions hash by identity, and
+/// floats by their bits, so a NaN key can never be found again since
+/// NaN isn't equal to itself
+impl Hash for Object {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Object::Nil => {}
+            Object::Bool(b) => b.hash(state),
+            Object::Int(n) => n.hash(state),
+            // 0.0 and -0.0 are equal so they have to hash the same
+            Object::Number(n) if *n == 0.0 => 0u64.hash(state),
+            Object::Number(n) => n.to_bits().hash(state),
+            Object::String(s) => s.hash(state),
+            Object::Callable(callable) => Rc::as_ptr(callable).cast::<()>().hash(state),
+            Object::Array(elements) => Rc::as_ptr(elements).hash(state),
+            Object::Map(entries) => Rc::as_ptr(entries).hash(state),
+            Object::Class(class) => Rc::as_ptr(class).hash(state),
+            Object::Instance(instance) => Rc::as_ptr(instance).hash(state),
+        }
+    }
+}
+
+impl Eq for Object {}
+
+/// Conversions for code embedding the interpreter, eg. building the
+/// arguments of a call with `3.into()` rather than `Object::Int(3)`
+impl From<bool> for Object {
+    fn from(b: bool) -> Self {
+        Object::Bool(b)
+    }
+}
+
+impl From<i64> for Object {
+    fn from(n: i64) -> Self {
+        Object::Int(n)
+    }
+}
+
+impl From<f64> for Object {
+    fn from(n: f64) -> Self {
+        Object::Number(n)
+    }
+}
+
+impl From<&str> for Object {
+    fn from(s: &str) -> Self {
+        Object::String(s.to_string())
+    }
+}
+
+impl From<String> for Object {
+    fn from(s: String) -> Self {
+        Object::String(s)
+    }
+}
+
+/// Ints convert too, so "3" can be read back as 3.0
+impl TryFrom<Object> for f64 {
+    type Error = Error;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        value.as_number().ok_or_else(|| mismatch("a number", &value))
+    }
+}
+
+impl TryFrom<Object> for String {
+    type Error = Error;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::String(s) => Ok(s),
+            _ => Err(mismatch("a string", &value)),
+        }
+    }
+}
+
+/// Only `true` and `false` convert, `Interpreter::is_truthy` tells
+/// whether any other value counts as true
+impl TryFrom<Object> for bool {
+    type Error = Error;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Bool(b) => Ok(b),
+            _ => Err(mismatch("a bool", &value)),
+        }
+    }
+}
+
+/// Like the errors from natives this has no line to report
+fn mismatch(expected: &str, value: &Object) -> Error {
+    Error::runtime_error(0, &format!("Expected {}, got {}.", expected, value.kind()))
+}
+
+/// Whole numbers are printed without a decimal point ("3" not "3.0"),
+/// and very large or very small numbers use exponent notation ("1e21")
+fn format_number(f: &mut std::fmt::Formatter<'_>, n: f64) -> std::fmt::Result {
+    if !n.is_finite() {
+        write!(f, "{}", n)
+    } else if n != 0.0 && (n.abs() >= 1e16 || n.abs() < 1e-6) {
+        write!(f, "{:e}", n)
+    } else if n.fract() == 0.0 {
+        write!(f, "{:.0}", n)
+    } else {
+        write!(f, "{}", n)
+    }
+}
+
+impl Object {
+    /// The value as a float if it is an int or a number
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Object::Int(n) => Some(*n as f64),
+            Object::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// The value as JSON, eg. for handing results to a web page. Whole
+    /// floats are written without the ".0", map keys are sorted and
+    /// anything JSON can't hold (functions, NaN, infinities and
+    /// collections that contain themselves) becomes null. Instances are
+    /// written as an object of their fields
+    pub fn to_json(&self) -> String {
+        let mut r = String::new();
+        write_json(&mut r, self, &mut Vec::new());
+        r
+    }
+
+    /// The value as the REPL echoes it, where a string is quoted so that
+    /// "hi" can't be mistaken for a variable named hi. Everything else
+    /// looks the same as when printed, like Python's repr vs str
+    pub fn repr(&self) -> String {
+        match self {
+            Object::String(s) => json_string(s),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Name of the kind of value this is, eg. "number"
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Object::Nil => "nil",
+            Object::Bool(_) => "bool",
+            Object::Int(_) | Object::Number(_) => "number",
+            Object::String(_) => "string",
+            Object::Callable(_) => "function",
+            Object::Array(_) => "array",
+            Object::Map(_) => "map",
+            Object::Class(_) => "class",
+            Object::Instance(_) => "instance",
+        }
+    }
+}
+
+/// Append `value` as JSON to `r`, `seen` works like in `write_nested`
+fn write_json(r: &mut String, value: &Object, seen: &mut Vec<*const ()>) {
+    match value {
+        Object::Nil => r.push_str("null"),
+        Object::Bool(b) => r.push_str(&b.to_string()),
+        Object::Int(n) => r.push_str(&n.to_string()),
+        Object::Number(n) if n.is_finite() => r.push_str(&n.to_string()),
+        Object::Number(_) => r.push_str("null"),
+        Object::String(s) => r.push_str(&json_string(s)),
+        Object::Callable(_) | Object::Class(_) => r.push_str("null"),
+        Object::Array(elements) => {
+            let ptr = Rc::as_ptr(elements).cast::<()>();
+            if seen.contains(&ptr) {
+                return r.push_str("null");
+            }
+
+            seen.push(ptr);
+            r.push('[');
+            for (i, element) in elements.borrow().iter().enumerate() {
+                if i > 0 {
+                    r.push(',');
+                }
+                write_json(r, element, seen);
+            }
+            r.push(']');
+            seen.pop();
+        }
+        Object::Map(entries) => write_json_entries(r, Rc::as_ptr(entries).cast(), &entries.borrow(), seen),
+        Object::Instance(instance) => {
+            write_json_entries(r, Rc::as_ptr(instance).cast(), &instance.fields.borrow(), seen)
+        }
+    }
+}
+
+/// Append the entries of a map or the fields of an instance as a JSON
+/// object, `ptr` is the map or instance for spotting cycles
+fn write_json_entries(r: &mut String, ptr: *const (), entries: &HashMap<String, Object>, seen: &mut Vec<*const ()>) {
+    if seen.contains(&ptr) {
+        return r.push_str("null");
+    }
+
+    seen.push(ptr);
+    let mut keys = entries.keys().collect::<Vec<&String>>();
+    keys.sort();
+    r.push('{');
+    for (i, key) in keys.into_iter().enumerate() {
+        if i > 0 {
+            r.push(',');
+        }
+        r.push_str(&json_string(key));
+        r.push(':');
+        write_json(r, &entries[key], seen);
+    }
+    r.push('}');
+    seen.pop();
+}
+
+/// Quote and escape a string so it can be embedded in JSON
+pub fn json_string(s: &str) -> String {
+    let mut r = String::with_capacity(s.len() + 2);
+    r.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => r.push_str("\\\""),
+            '\\' => r.push_str("\\\\"),
+            '\n' => r.push_str("\\n"),
+            '\r' => r.push_str("\\r"),
+            '\t' => r.push_str("\\t"),
+            c if (c as u32) < 0x20 => r.push_str(&format!("\\u{:04x}", c as u32)),
+            c => r.push(c),
         }
     }
+    r.push('"');
+    r
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -25,26 +382,49 @@ pub struct Token {
     pub lexeme: String,
     pub literal: Object,
     pub line: u32,
+    /// 1-based column of the first character of the lexeme
+    pub column: u32,
+    /// Byte offsets of the lexeme in the source, see `span`
+    pub start: usize,
+    pub end: usize,
 }
 
+/// "line:column Type 'lexeme' literal", eg. "1:9 Number '1.5' 1.5".
+/// The literal is left off for tokens which don't have one
 impl Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "")
+        write!(f, "{}:{} {:?} '{}'", self.line, self.column, self.type_, self.lexeme)?;
+        match self.literal {
+            Object::Nil => Ok(()),
+            _ => write!(f, " {}", self.literal),
+        }
     }
 }
 
 impl Token {
-    pub fn new(type_: TokenType, lexeme: String, literal: Object, line: u32) -> Token {
+    pub fn new(type_: TokenType, lexeme: String, literal: Object, line: u32, column: u32) -> Token {
         Token {
             type_,
             lexeme,
             literal,
             line,
+            column,
+            start: 0,
+            end: 0,
         }
     }
 
-    fn to_string(&self) -> String {
-        format!("{:?} {} {}", self.type_, self.lexeme, self.literal)
+    /// Set where in the source the token came from
+    pub fn with_span(mut self, span: Range<usize>) -> Token {
+        self.start = span.start;
+        self.end = span.end;
+        self
+    }
+
+    /// The byte range of the lexeme in the source it was scanned from,
+    /// eg. for an editor to highlight it. Empty for the EOF token
+    pub fn span(&self) -> Range<usize> {
+        self.start..self.end
     }
 }
 
@@ -55,13 +435,21 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
     Plus,
+    Question,
     Semicolon,
     Slash,
     Star,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
 
     // One or two character tokens.
     Bang,
@@ -72,20 +460,38 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
+    PlusPlus,
+    MinusMinus,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    StarStar,
+    QuestionQuestion,
 
     // Literals.
     Identifier,
     String,
     Number,
+    /// The part of a string before a "${", followed by the tokens of
+    /// the embedded expression. The string carries on after the "}"
+    Interpolation,
 
     // Keywords.
     And,
+    Assert,
+    Break,
     Class,
+    Const,
+    Continue,
     Else,
     False,
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
@@ -96,5 +502,118 @@ pub enum TokenType {
     Var,
     While,
 
+    // Trivia, only scanned by `Scanner::with_trivia`.
+    Comment,
+
     Eof,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_numbers() {
+        assert!(Object::Int(1) < Object::Int(2));
+        assert!(Object::Number(1.5) > Object::Int(1));
+        assert!(Object::Int(-3) < Object::Number(-2.5));
+        assert!(Object::Int(1) < Object::Number(1.0));
+        assert_eq!(Object::Number(f64::NAN).partial_cmp(&Object::Number(1.0)), None);
+
+        let mut numbers = vec![Object::Number(2.5), Object::Int(3), Object::Int(-1), Object::Number(0.0)];
+        numbers.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(numbers, vec![Object::Int(-1), Object::Number(0.0), Object::Number(2.5), Object::Int(3)]);
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(Object::from(true), Object::Bool(true));
+        assert_eq!(Object::from(3), Object::Int(3));
+        assert_eq!(Object::from(1.5), Object::Number(1.5));
+        assert_eq!(Object::from("hi"), Object::String("hi".to_string()));
+        assert_eq!(Object::from("hi".to_string()), Object::String("hi".to_string()));
+
+        assert_eq!(f64::try_from(Object::from(1.5)).unwrap(), 1.5);
+        assert_eq!(f64::try_from(Object::from(3)).unwrap(), 3.0);
+        assert_eq!(String::try_from(Object::from("hi")).unwrap(), "hi");
+        assert!(bool::try_from(Object::from(false)).is_ok_and(|b| !b));
+
+        for (result, expected) in [
+            (f64::try_from(Object::from("1")).map(|_| ()), "Expected a number, got string."),
+            (String::try_from(Object::Nil).map(|_| ()), "Expected a string, got nil."),
+            (bool::try_from(Object::from(1)).map(|_| ()), "Expected a bool, got number."),
+        ] {
+            match result {
+                Err(Error::RuntimeError { message, .. }) => assert_eq!(message, expected),
+                _ => panic!("expected a runtime error"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_repr() {
+        assert_eq!(Object::String("hi".to_string()).repr(), "\"hi\"");
+        assert_eq!(Object::String("say \"hi\"".to_string()).repr(), "\"say \\\"hi\\\"\"");
+        assert_eq!(Object::Nil.repr(), "nil");
+        assert_eq!(Object::Number(1.5).repr(), "1.5");
+        assert_eq!(Object::Bool(true).repr(), "true");
+        assert_eq!(Object::String("hi".to_string()).to_string(), "hi");
+    }
+
+    #[test]
+    fn test_order_strings() {
+        let a = Object::String("apple".to_string());
+        let b = Object::String("banana".to_string());
+        assert!(a < b);
+        assert_eq!(a.partial_cmp(&a.clone()), Some(Ordering::Equal));
+        assert_eq!(a.partial_cmp(&Object::Int(1)), None);
+        assert_eq!(Object::Nil.partial_cmp(&Object::Bool(false)), None);
+    }
+
+    #[test]
+    // functions hold a RefCell but hash by pointer, so keys can't change
+    #[allow(clippy::mutable_key_type)]
+    fn test_object_as_map_key() {
+        let mut counts = std::collections::HashMap::new();
+        *counts.entry(Object::String("a".to_string())).or_insert(0) += 1;
+        *counts.entry(Object::String("a".to_string())).or_insert(0) += 1;
+        *counts.entry(Object::Int(1)).or_insert(0) += 1;
+        *counts.entry(Object::Number(0.0)).or_insert(0) += 1;
+        *counts.entry(Object::Number(-0.0)).or_insert(0) += 1;
+
+        assert_eq!(counts[&Object::String("a".to_string())], 2);
+        assert_eq!(counts[&Object::Int(1)], 1);
+        assert_eq!(counts[&Object::Number(0.0)], 2);
+        assert!(!counts.contains_key(&Object::Number(1.0)));
+    }
+
+    #[test]
+    fn test_display_token() {
+        let number = Token::new(TokenType::Number, "1.5".to_string(), Object::Number(1.5), 2, 9);
+        assert_eq!(number.to_string(), "2:9 Number '1.5' 1.5");
+
+        let plus = Token::new(TokenType::Plus, "+".to_string(), Object::Nil, 1, 3);
+        assert_eq!(plus.to_string(), "1:3 Plus '+'");
+    }
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(Object::Nil.to_json(), "null");
+        assert_eq!(Object::Bool(true).to_json(), "true");
+        assert_eq!(Object::Int(42).to_json(), "42");
+        assert_eq!(Object::Number(42.0).to_json(), "42");
+        assert_eq!(Object::Number(2.5).to_json(), "2.5");
+        assert_eq!(Object::Number(f64::NAN).to_json(), "null");
+        assert_eq!(Object::String("say \"hi\"\n".to_string()).to_json(), "\"say \\\"hi\\\"\\n\"");
+
+        let array = Rc::new(RefCell::new(vec![Object::Int(1), Object::String("a".to_string())]));
+        let map = HashMap::from([
+            ("b".to_string(), Object::Array(array.clone())),
+            ("a".to_string(), Object::Nil),
+        ]);
+        assert_eq!(Object::Map(Rc::new(RefCell::new(map))).to_json(), "{\"a\":null,\"b\":[1,\"a\"]}");
+
+        array.borrow_mut().push(Object::Array(array.clone()));
+        assert_eq!(Object::Array(array).to_json(), "[1,\"a\",null]");
+    }
+}
@@ -1,26 +1,169 @@
 use wasm_bindgen::prelude::*;
 
-use crate::{interpreter::Interpreter, scanner::Scanner, parser::Parser};
+use crate::{error::Error, interpreter::Interpreter, scanner::Scanner, parser::Parser, token::{json_string, Object}};
 
+/// How many statements a program in the browser may run before it is
+/// stopped, so an infinite loop doesn't freeze the tab
+pub const STEP_LIMIT: usize = 10_000_000;
+
+/// How deeply code may nest in the browser, lower than the parser's
+/// `DEFAULT_MAX_DEPTH` for the same reason as `MAX_CALL_DEPTH`
+pub const MAX_DEPTH: usize = 64;
+
+/// How deeply functions may call each other in the browser. Its stack
+/// is a fixed 1MB that can't be grown like `runner::with_large_stack`
+/// does, so this is lower than `DEFAULT_MAX_CALL_DEPTH`
+pub const MAX_CALL_DEPTH: usize = 250;
+
+/// Run `code` in the browser, returning everything it printed. Any
+/// errors are thrown back to JS as a string
 #[wasm_bindgen]
 pub fn execute_code(code: &str) -> Result<String, JsValue> {
-    let interpreter = Interpreter::new();
+    run_code(code).map_err(|e| JsValue::from_str(&e))
+}
+
+/// Like `execute_code`, but returns JSON for JS to `JSON.parse`:
+/// `{"output":["..."],"result":42}` where `result` is the value of the
+/// last statement when it is a bare expression and null otherwise
+#[wasm_bindgen]
+pub fn execute_code_json(code: &str) -> Result<String, JsValue> {
+    run_code_json(code).map_err(|e| JsValue::from_str(&e))
+}
+
+/// Scan `code` for syntax highlighting, returning JSON for JS to
+/// `JSON.parse`: an array of `{"type":"Identifier","lexeme":"a","start":0,"end":1,"line":1}`
+/// in source order, where `start` and `end` are byte offsets. Comments
+/// are included, and scanner errors show up as
+/// `{"type":"error","message":"...","start":3,"end":3,"line":1}`
+/// where they happened rather than stopping the scan
+#[wasm_bindgen]
+pub fn tokenize(code: &str) -> String {
+    tokens_json(code)
+}
+
+/// The JSON array of tokens and errors returned by `tokenize`
+pub fn tokens_json(code: &str) -> String {
+    let entries = Scanner::new(code)
+        .with_trivia(true)
+        .map(|token| match token {
+            Ok(token) => format!(
+                "{{\"type\":{},\"lexeme\":{},\"start\":{},\"end\":{},\"line\":{}}}",
+                json_string(&format!("{:?}", token.type_)),
+                json_string(&token.lexeme),
+                token.start,
+                token.end,
+                token.line
+            ),
+            Err(Error::ScannerError { message, line, offset, .. }) => {
+                format!(
+                    "{{\"type\":\"error\",\"message\":{},\"start\":{},\"end\":{},\"line\":{}}}",
+                    json_string(&message),
+                    offset,
+                    offset,
+                    line
+                )
+            }
+            Err(e) => unreachable!("the scanner only makes scanner errors, got {:?}", e),
+        })
+        .collect::<Vec<String>>();
+    format!("[{}]", entries.join(","))
+}
+
+/// Run `code`, returning its printed output (one line per `print`) or
+/// every error that stopped it from running
+pub fn run_code(code: &str) -> Result<String, String> {
+    let (interpreter, _) = interpret_code(code)?;
+    Ok(interpreter.output().join("\n"))
+}
+
+/// Run `code`, returning what it printed and the value of its last
+/// expression as JSON, see `execute_code_json`
+pub fn run_code_json(code: &str) -> Result<String, String> {
+    let (interpreter, result) = interpret_code(code)?;
+
+    let output = interpreter
+        .output()
+        .iter()
+        .map(|line| json_string(line))
+        .collect::<Vec<String>>();
+    let result = result.unwrap_or(Object::Nil);
+    Ok(format!("{{\"output\":[{}],\"result\":{}}}", output.join(","), result.to_json()))
+}
+
+/// Scan, parse and run `code` with its output captured, giving back the
+/// interpreter and the value of the last statement when it is a bare
+/// expression
+fn interpret_code(code: &str) -> Result<(Interpreter, Option<Object>), String> {
     let mut scanner = Scanner::new(code);
-    let tokens = scanner.scan_tokens();
+    let tokens = scanner
+        .scan_tokens()
+        .map_err(|_| format_errors(scanner.errors()))?;
+
+    let mut parser = Parser::new(tokens).with_max_depth(MAX_DEPTH);
+    let statements = parser
+        .parse()
+        .map_err(|_| format_errors(parser.errors()))?;
+
+    let mut interpreter = Interpreter::new()
+        .with_captured_output()
+        .with_step_limit(STEP_LIMIT)
+        .with_max_call_depth(MAX_CALL_DEPTH);
+    let result = interpreter
+        .interpret_value(&statements)
+        .map_err(|e| e.to_string())?;
+    Ok((interpreter, result))
+}
+
+fn format_errors(errors: &[Error]) -> String {
+    errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
 
-    let mut parser = Parser::new(tokens);
-    let expression_res = parser.parse();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn test_run_code_output() {
+        assert_eq!(run_code("print 1+2;").unwrap(), "3");
+        assert_eq!(run_code("print 1; print 2;").unwrap(), "1\n2");
+    }
 
-    if let Ok(expression) = expression_res {
-        let result = interpreter.interpret(&expression);
-        match result {
-            Ok(result) => return Ok(result.to_string()),
-            Err(e) => eprintln!("Error: {:?}", e),
-        }
-    } else {
-        eprintln!("Expression error: {:?}", expression_res.err());
+    #[test]
+    fn test_run_code_errors() {
+        assert!(run_code("print ;").unwrap_err().contains("Parse error"));
+        assert!(run_code("print -nil;").unwrap_err().contains("Runtime error"));
+        assert_eq!(run_code("@ #").unwrap_err().lines().count(), 2);
     }
 
-    Ok("Execution result".to_string())
+    #[test]
+    fn test_run_code_json() {
+        assert_eq!(run_code_json("print \"hi\"; [1, 2.0, nil];").unwrap(), "{\"output\":[\"hi\"],\"result\":[1,2,null]}");
+        assert_eq!(run_code_json("var a = 1;").unwrap(), "{\"output\":[],\"result\":null}");
+        assert!(run_code_json("print -nil;").unwrap_err().contains("Runtime error"));
+    }
+
+    #[test]
+    fn test_tokens_json() {
+        let tokens = tokens_json("var a = 1; // one");
+        assert_eq!(tokens.matches("\"type\"").count(), 7);
+        assert!(tokens.starts_with("[{\"type\":\"Var\",\"lexeme\":\"var\",\"start\":0,\"end\":3,\"line\":1},"));
+        assert!(tokens.contains("{\"type\":\"Comment\",\"lexeme\":\"// one\",\"start\":11,\"end\":17,\"line\":1}"));
+
+        let tokens = tokens_json("a\n @ b");
+        assert_eq!(tokens.matches("\"type\"").count(), 4);
+        assert!(tokens.contains("{\"type\":\"error\",\"message\":\"Unexpected character '@'.\",\"start\":3,\"end\":3,\"line\":2}"));
+
+        // a "\r" on its own is a line break to the scanner
+        let tokens = tokens_json("a\r@");
+        assert!(tokens.contains("{\"type\":\"error\",\"message\":\"Unexpected character '@'.\",\"start\":2,\"end\":2,\"line\":2}"));
+    }
+
+    #[test]
+    fn test_run_code_infinite_loop() {
+        assert!(run_code("while (true) {}").unwrap_err().contains("Step limit exceeded."));
+    }
 }